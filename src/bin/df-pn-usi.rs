@@ -0,0 +1,229 @@
+use df_pn::{reset_db, Board, CheckmateResult, Move, SearchOptions, StorageConfig};
+use std::{
+    io::{stdin, stdout, BufRead, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+const DEFAULT_DB_PATH: &str = "/tmp/df_pn.usi.rocksdb";
+const DEFAULT_HASH_MB: usize = 1024;
+
+enum Event {
+    Command(String),
+    Finished(String),
+}
+
+struct Engine {
+    db_path: String,
+    /// Size in bytes of the block cache, or of the in-memory table if `memory_table` is set.
+    hash_size: usize,
+    memory_table: bool,
+    max_depth: Option<usize>,
+    threads: usize,
+    exclude_useless_interpositions: bool,
+    position: Option<(Board, bool)>,
+    /// The last search and its stop flag, kept to be joined before the database is opened again.
+    search: Option<(JoinHandle<()>, Arc<AtomicBool>)>,
+}
+
+impl Engine {
+    fn new() -> Engine {
+        Engine {
+            db_path: String::from(DEFAULT_DB_PATH),
+            hash_size: DEFAULT_HASH_MB * 1024 * 1024,
+            memory_table: false,
+            max_depth: None,
+            threads: 1,
+            exclude_useless_interpositions: false,
+            position: None,
+            search: None,
+        }
+    }
+
+    /// Handles a command and returns `false` on `quit`.
+    fn command(&mut self, line: &str, events: &Sender<Event>) -> bool {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("usi") => {
+                println!("id name df-pn");
+                println!("id author malt03");
+                println!(
+                    "option name USI_Hash type spin default {DEFAULT_HASH_MB} min 1 max 1048576"
+                );
+                println!("option name DbPath type string default {DEFAULT_DB_PATH}");
+                println!("option name MaxDepth type spin default 0 min 0 max 10000");
+                println!("option name MemoryTable type check default false");
+                println!("option name Threads type spin default 1 min 1 max 256");
+                println!("option name ExcludeUselessInterpositions type check default false");
+                println!("usiok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("setoption") => self.set_option(line),
+            Some("position") => {
+                let args = line.trim_start()["position".len()..].trim();
+                self.position = match Board::from_usi_position(args) {
+                    Ok(position) => Some(position),
+                    Err(e) => {
+                        println!("info string failed to parse position: {e}");
+                        None
+                    }
+                };
+            }
+            Some("go") => match tokens.next() {
+                Some("mate") => {
+                    let deadline = match tokens.next() {
+                        Some("infinite") | None => None,
+                        Some(ms) => ms
+                            .parse()
+                            .ok()
+                            .map(|ms| Instant::now() + Duration::from_millis(ms)),
+                    };
+                    self.go_mate(deadline, events);
+                }
+                _ => println!("bestmove resign"),
+            },
+            Some("stop") => {
+                if let Some((_, stop)) = &self.search {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+            Some("quit") => {
+                self.join();
+                return false;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn set_option(&mut self, line: &str) {
+        let Some((name, value)) = line
+            .split_once(" name ")
+            .and_then(|(_, option)| option.split_once(" value "))
+        else {
+            return;
+        };
+        let value = value.trim();
+        match name.trim() {
+            "USI_Hash" => {
+                if let Ok(mb) = value.parse::<usize>() {
+                    self.hash_size = mb * 1024 * 1024;
+                }
+            }
+            "DbPath" => self.db_path = value.to_string(),
+            "MemoryTable" => self.memory_table = value == "true",
+            "ExcludeUselessInterpositions" => self.exclude_useless_interpositions = value == "true",
+            "Threads" => {
+                if let Ok(threads) = value.parse::<usize>() {
+                    self.threads = threads.max(1);
+                }
+            }
+            "MaxDepth" => {
+                if let Ok(depth) = value.parse::<usize>() {
+                    self.max_depth = (depth > 0).then_some(depth);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn go_mate(&mut self, deadline: Option<Instant>, events: &Sender<Event>) {
+        // A GUI always sends a position first, so the command is ignored as a mistake without one.
+        let Some((board, is_black_turn)) = self.position.clone() else {
+            eprintln!("go mate without a valid position");
+            return;
+        };
+        self.join();
+        if !self.memory_table {
+            if let Err(e) = reset_db(&self.db_path, false) {
+                println!("info string {e}");
+                println!("checkmate notimplemented");
+                return;
+            }
+        }
+
+        let db_path = self.db_path.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let options = SearchOptions {
+            max_depth: self.max_depth.map(|d| d + 2),
+            deadline,
+            stop: Some(stop.clone()),
+            table_size: self.memory_table.then_some(self.hash_size),
+            threads: self.threads,
+            exclude_useless_interpositions: self.exclude_useless_interpositions,
+            storage: StorageConfig {
+                cache_size: self.hash_size,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let events = events.clone();
+        let search = thread::spawn(move || {
+            let answer = match board.get_checkmate_moves(db_path, false, &options) {
+                CheckmateResult::Checkmate(moves, _) => {
+                    let moves: Vec<_> = moves.iter().map(|mv| to_usi(mv, is_black_turn)).collect();
+                    format!("checkmate {}", moves.join(" "))
+                }
+                CheckmateResult::NotCheckmate(_, _) => String::from("checkmate nomate"),
+                CheckmateResult::Unproven(_) => String::from("checkmate timeout"),
+            };
+            let _ = events.send(Event::Finished(answer));
+        });
+        self.search = Some((search, stop));
+    }
+
+    /// Stops the last search, if any, and waits for it to release the database.
+    fn join(&mut self) {
+        if let Some((search, stop)) = self.search.take() {
+            stop.store(true, Ordering::Relaxed);
+            search.join().unwrap();
+        }
+    }
+}
+
+/// Moves are returned for the board seen from the side to move, which is reversed when white is to move.
+fn to_usi(mv: &Move, is_black_turn: bool) -> String {
+    if is_black_turn {
+        mv.to_usi()
+    } else {
+        mv.reversed().to_usi()
+    }
+}
+
+fn main() {
+    let (sender, receiver) = channel();
+    let commands = sender.clone();
+    thread::spawn(move || {
+        for line in stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if commands.send(Event::Command(line)).is_err() {
+                break;
+            }
+        }
+        let _ = commands.send(Event::Command(String::from("quit")));
+    });
+
+    let mut engine = Engine::new();
+    while let Ok(event) = receiver.recv() {
+        match event {
+            Event::Command(line) => {
+                if !engine.command(&line, &sender) {
+                    // Answer a `stop` that came right before `quit`.
+                    for event in receiver.try_iter() {
+                        if let Event::Finished(answer) = event {
+                            println!("{answer}");
+                        }
+                    }
+                    break;
+                }
+            }
+            Event::Finished(answer) => println!("{answer}"),
+        }
+        stdout().flush().unwrap();
+    }
+}
@@ -1,17 +1,31 @@
+mod bitboard;
 mod control_map;
 mod create_all_next_boards;
 mod create_all_next_boards_test;
+mod do_move;
 mod get_checkmate_board;
 mod is_checked;
+mod is_useless_interposition;
+mod kif;
+mod moves;
 mod pieces;
+mod sfen;
+mod zobrist;
 
-use crate::{db::Key, shared::Set};
+use crate::{db::Key, shared::Set, ParseError, ParseErrorReason::*};
 use bincode::{Decode, Encode};
+pub(crate) use bitboard::{Bitboard, Bitboards};
 use colored::Colorize;
 pub(crate) use control_map::{get_vectors, CONTROL_MAP};
-pub(crate) use create_all_next_boards::NextBoardKind;
-pub use get_checkmate_board::CheckmateResult;
-pub(crate) use pieces::{Coord, Kind as PieceKind, Piece, Pieces, Status as PieceStatus};
+pub use get_checkmate_board::{
+    AlternativeMate, CheckmateResult, Checkpoint, MismatchReason, Progress, ProgressObserver,
+    SearchOptions, Verification,
+};
+pub(crate) use moves::RANKS;
+pub use moves::{Move, MoveTree};
+pub(crate) use pieces::{
+    Coord, Kind as PieceKind, Locations, Piece, Pieces, Status as PieceStatus,
+};
 use std::{
     collections::HashMap,
     hash::{Hash, Hasher},
@@ -22,15 +36,22 @@ use PieceStatus::*;
 const UP_ARROW: char = '￪';
 const DOWN_ARROW: char = '￬';
 
+/// The pieces are listed by kind to tell them apart in the moves, while what is on each square, the hands
+/// and the controls are read from `bitboards`, which is kept up to date with them.
 #[derive(Clone, Debug, Encode, Decode)]
 pub struct Board {
     pub(crate) pieces: Pieces,
-    pub(crate) board_map: Vec<Vec<Option<(PieceKind, usize)>>>,
+    pub(crate) bitboards: Bitboards,
+    /// Zobrist hash of `pieces`, kept up to date with `bitboards`.
+    pub(crate) hash: u64,
+    /// Whether `My` is black, as read from SFEN and written back by `to_sfen`.
+    pub(crate) is_black: bool,
+    pub(crate) move_number: u32,
 }
 
 impl Hash for Board {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.pieces.hash(state);
+        self.hash.hash(state);
     }
 }
 
@@ -58,84 +79,101 @@ pub(crate) const BOARD_SIZE: usize = 9;
 
 impl Board {
     pub(crate) fn key(&self) -> Key {
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-        self.hash(&mut hasher);
-        let hash = hasher.finish();
-
-        let mut key = [0; 8];
-        for i in 0..8 {
-            key[i] = (hash >> (i * 8)) as u8;
-        }
-        key
+        self.hash.to_le_bytes()
     }
 
     pub(crate) fn new(pieces: Pieces) -> Board {
         Board {
             pieces,
-            board_map: vec![vec![None; BOARD_SIZE]; BOARD_SIZE],
+            bitboards: Bitboards::default(),
+            hash: 0,
+            is_black: true,
+            move_number: 1,
         }
     }
 
-    #[cfg(test)]
     pub(crate) fn first() -> Board {
         let mut board = Board::new(Pieces::first());
-        board.reload_board_map();
+        board.reload_bitboards();
         board
     }
 
     #[cfg(test)]
     pub(crate) fn all_catched() -> Board {
         let mut board = Board::new(Pieces::all_catched());
-        board.reload_board_map();
+        board.reload_bitboards();
         board
     }
 
-    pub fn parsed<S>(str: S) -> Board
+    pub fn parsed<S>(str: S) -> std::result::Result<Board, ParseError>
+    where
+        S: AsRef<str>,
+    {
+        Board::parse(str, false)
+    }
+
+    /// Same as `parsed`, but the pieces missing from the diagram go to the enemy's hand,
+    /// as usual in tsume problems. Only too many pieces of a kind is an error.
+    pub fn parsed_partial<S>(str: S) -> std::result::Result<Board, ParseError>
+    where
+        S: AsRef<str>,
+    {
+        Board::parse(str, true)
+    }
+
+    fn parse<S>(str: S, fill_enemy_hands: bool) -> std::result::Result<Board, ParseError>
     where
         S: AsRef<str>,
     {
         let mut pieces = Pieces::new();
+        let mut locations = Locations::new();
+        let unexpected_end = |line: usize| ParseError::new(UnexpectedEnd, None).at(line, 1);
 
         let mut lines = str.as_ref().lines();
-        let enemy_hands = PieceKind::parse_hands(lines.next().unwrap());
+        let enemy_hands = PieceKind::parse_hands(lines.next().ok_or(unexpected_end(1))?, 1)?;
         for (kind, n) in enemy_hands {
             pieces[kind].extend((0..n).map(|_| Piece::catched(false)));
         }
         lines.next();
 
         for y in 0..BOARD_SIZE as i8 {
-            let line = lines.next().unwrap();
-            let mut chars = line.chars();
+            let line_number = y as usize + 3;
+            let line = lines.next().ok_or(unexpected_end(line_number))?;
+            let mut chars = line.chars().zip(1..);
             for x in 0..BOARD_SIZE as i8 {
-                let Some(c) = chars.next() else { break };
+                let Some((c, column)) = chars.next() else {
+                    break;
+                };
                 let status = match c {
                     ' ' => {
                         for space in (0..2).map(|_| chars.next()) {
-                            let Some(space) = space else { break };
+                            let Some((space, column)) = space else { break };
                             if space != ' ' {
-                                panic!("Invalid space: {space} ({x},{y})");
+                                return Err(ParseError::new(UnknownGlyph, Some(space))
+                                    .at(line_number, column));
                             }
                         }
                         continue;
                     }
                     UP_ARROW => MyBoard,
                     DOWN_ARROW => EnemyBoard,
-                    _ => panic!("Invalid arrow: {c} ({x},{y})"),
+                    _ => return Err(ParseError::new(UnknownGlyph, Some(c)).at(line_number, column)),
                 };
 
-                let c = chars
+                let (c, column) = chars
                     .next()
-                    .expect(format!("Empty char: ({x},{y})").as_str());
+                    .ok_or(ParseError::new(UnexpectedEnd, None).at(line_number, column + 1))?;
                 let (kind, is_changed) = PieceKind::safe_parse(c)
-                    .expect(format!("Invalid char: {c} ({x},{y})").as_str());
+                    .ok_or(ParseError::new(UnknownGlyph, Some(c)).at(line_number, column))?;
 
                 pieces[kind].push(Piece::new(x, y, status, is_changed));
+                locations.insert(Coord::new(x, y), (line_number, column));
             }
         }
 
         lines.next();
         let my_hands = if let Some(line) = lines.next() {
-            PieceKind::parse_hands(line)
+            PieceKind::parse_hands(line, BOARD_SIZE + 4)?
         } else {
             HashMap::new()
         };
@@ -143,44 +181,49 @@ impl Board {
             pieces[kind].extend((0..n).map(|_| Piece::catched(true)));
         }
 
-        pieces.validate_len();
+        if fill_enemy_hands {
+            pieces.fill_remaining(false);
+        }
+        pieces.validate(&locations)?;
 
         let mut board = Board::new(pieces);
-        board.reload_board_map();
-        board
+        board.reload_bitboards();
+        Ok(board)
     }
 
     pub fn reversed(&self) -> Board {
-        let pieces = self.pieces.map(|p| Piece {
-            coord: Coord {
-                x: BOARD_SIZE as i8 - p.coord.x - 1,
-                y: BOARD_SIZE as i8 - p.coord.y - 1,
-            },
-            is_changed: p.is_changed,
-            status: p.status.reversed(),
+        let pieces = self.pieces.map(|p| {
+            if !p.status.on_board() {
+                return Piece::catched(p.status == EnemyHand);
+            }
+            Piece {
+                coord: Coord {
+                    x: BOARD_SIZE as i8 - p.coord.x - 1,
+                    y: BOARD_SIZE as i8 - p.coord.y - 1,
+                },
+                is_changed: p.is_changed,
+                status: p.status.reversed(),
+            }
         });
         let mut board = Board::new(pieces);
-        board.reload_board_map();
+        board.reload_bitboards();
+        board.is_black = !self.is_black;
+        board.move_number = self.move_number;
         board
     }
 
-    pub(crate) fn reload_board_map(&mut self) {
-        for y in 0..BOARD_SIZE {
-            for x in 0..BOARD_SIZE {
-                self.board_map[y][x] = None;
-            }
-        }
+    /// Rebuilds `bitboards` and the hash from `pieces` after they are set by hand.
+    pub(crate) fn reload_bitboards(&mut self) {
+        self.bitboards = Bitboards::default();
         for (kind, i, p) in self.pieces.iter() {
-            if p.status.on_board() {
-                self.board_map[p.coord.y as usize][p.coord.x as usize] = Some((kind, i));
-            }
+            self.bitboards.insert(kind, i, p);
         }
+        self.hash = self.zobrist_hash();
     }
 
     pub(crate) fn piece_at(&self, coord: &Coord) -> Option<(&Piece, PieceKind, usize)> {
-        let Some((kind, i)) = self.board_map[coord.y as usize][coord.x as usize] else {
-            return None;
-        };
+        let (_, kind) = self.bitboards.at(*coord)?;
+        let i = self.bitboards.index_at(*coord);
         Some((&self[kind][i], kind, i))
     }
 
@@ -239,13 +282,12 @@ impl Board {
             .join(" ");
         writeln!(w, "{enemy_hands_str}\n------------------")?;
 
-        for row in &self.board_map {
-            for i in row {
-                let Some((kind, i)) = *i else {
+        for y in 0..BOARD_SIZE as i8 {
+            for x in 0..BOARD_SIZE as i8 {
+                let Some((p, kind, _)) = self.piece_at(&Coord::new(x, y)) else {
                     write!(w, "{}", if colored { "  " } else { "   " })?;
                     continue;
                 };
-                let p = &self[kind][i];
                 match p.status {
                     MyBoard => write!(w, "{}{}", arrow(true), m(kind, p.is_changed))?,
                     EnemyBoard => write!(w, "{}{}", arrow(false), e(kind, p.is_changed))?,
@@ -298,7 +340,7 @@ mod tests {
         board[Fu][0] = Piece::catched(true);
         board[Kyousha][0] = Piece::catched(false);
         board[Kaku][0] = Piece::new(1, 4, EnemyBoard, true);
-        board.reload_board_map();
+        board.reload_bitboards();
 
         assert_eq_board(
             &board,
@@ -336,6 +378,136 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_parse_error() {
+        let str = "香x2 金
+------------------
+￬香￬桂￬銀   ￬王￬金￬銀￬桂   
+   ￬飛               ￬角   
+￬歩￬歩￬歩￬歩￬歩￬歩￬歩￬歩￬歩
+   ￬犬                     
+";
+        assert_eq!(
+            Board::parsed(str),
+            Err(ParseError::new(UnknownGlyph, Some('犬')).at(6, 5))
+        );
+        assert_eq!(
+            Board::parsed("歩x200 歩x200\n"),
+            Err(ParseError::new(InvalidHandCount, Some('歩')).at(1, 7))
+        );
+
+        let str = "歩x17 香x4 桂x4 銀x4 金x4 角x2 飛x2
+------------------
+            ￪歩            
+            ￬王            
+                           
+                           
+                           
+                           
+                           
+                           
+                           
+------------------
+";
+        assert_eq!(
+            Board::parsed(str),
+            Err(ParseError::new(PawnOnLastRank, Some('歩')).at(3, 14))
+        );
+
+        let str = "歩x16 香x4 桂x4 銀x4 金x4 角x2 飛x2
+------------------
+            ￬王            
+                           
+                           
+                           
+            ￪歩            
+            ￪歩            
+                           
+                           
+                           
+------------------
+";
+        assert_eq!(Board::parsed(str).unwrap_err().reason, DoublePawn);
+
+        let str = "歩x18 香x4 桂x4 銀x4 金x3 角x2 飛x2
+------------------
+            ￬王            
+                           
+                           
+                           
+                           
+                           
+                           
+                           
+                           
+------------------
+";
+        assert_eq!(
+            Board::parsed(str),
+            Err(ParseError::new(
+                WrongPieceTotal {
+                    len: 3,
+                    expected: 4
+                },
+                Some('金')
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let str = "
+------------------
+            ￬王            
+                           
+            ￪歩            
+                           
+                           
+                           
+                           
+                           
+                           
+------------------
+金 飛";
+        let board = Board::parsed_partial(str).unwrap();
+        assert_eq_board(
+            &board,
+            "
+歩x17 香x4 桂x4 銀x4 金x3 角x2 飛
+------------------
+            ￬王            
+                           
+            ￪歩            
+                           
+                           
+                           
+                           
+                           
+                           
+------------------
+金 飛",
+        );
+        assert_eq!(
+            Board::parsed(str).unwrap_err().reason,
+            WrongPieceTotal {
+                len: 1,
+                expected: 18
+            }
+        );
+
+        let str = str.replace("金 飛", "金 飛x3");
+        assert_eq!(
+            Board::parsed_partial(str),
+            Err(ParseError::new(
+                WrongPieceTotal {
+                    len: 3,
+                    expected: 2
+                },
+                Some('飛')
+            ))
+        );
+    }
+
     #[test]
     fn test_parse() {
         let str = "香x2 金
@@ -351,7 +523,7 @@ mod tests {
    ￪桂￪銀￪金￪王￪金￪銀￪桂￪香
 ------------------
 歩";
-        let board = Board::parsed(str);
+        let board = Board::parsed(str).unwrap();
         let mut output = String::new();
         board.dump_to(&mut output, false).unwrap();
         assert_eq!(output.to_string(), str);
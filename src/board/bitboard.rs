@@ -0,0 +1,407 @@
+use super::{Coord, Piece, PieceKind, PieceStatus, BOARD_SIZE, CONTROL_MAP};
+use bincode::{Decode, Encode};
+use once_cell::sync::Lazy;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, Not};
+use PieceKind::*;
+use PieceStatus::*;
+
+const SQUARES: usize = BOARD_SIZE * BOARD_SIZE;
+
+/// A set of squares, where the bit `y * 9 + x` stands for the square at `(x, y)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Encode, Decode)]
+pub(crate) struct Bitboard(u128);
+
+impl Bitboard {
+    const ALL: Bitboard = Bitboard((1 << SQUARES) - 1);
+
+    fn index(coord: Coord) -> usize {
+        coord.y as usize * BOARD_SIZE + coord.x as usize
+    }
+
+    fn coord(index: usize) -> Coord {
+        Coord::new((index % BOARD_SIZE) as i8, (index / BOARD_SIZE) as i8)
+    }
+
+    pub(crate) fn square(coord: Coord) -> Bitboard {
+        Bitboard(1 << Bitboard::index(coord))
+    }
+
+    /// The squares of the file `x`.
+    pub(crate) fn file(x: i8) -> Bitboard {
+        (0..BOARD_SIZE as i8).fold(Bitboard::default(), |file, y| {
+            file | Bitboard::square(Coord::new(x, y))
+        })
+    }
+
+    pub(crate) fn contains(self, coord: Coord) -> bool {
+        self.0 >> Bitboard::index(coord) & 1 == 1
+    }
+
+    pub(crate) fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The squares by rank and then by file, in the order of the other move generation loops.
+    pub(crate) fn iter(self) -> impl Iterator<Item = Coord> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let index = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            Some(Bitboard::coord(index))
+        })
+    }
+
+    fn first(self) -> Option<usize> {
+        (!self.is_empty()).then(|| self.0.trailing_zeros() as usize)
+    }
+
+    fn last(self) -> Option<usize> {
+        (!self.is_empty()).then(|| 127 - self.0.leading_zeros() as usize)
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 & other.0)
+    }
+}
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, other: Bitboard) {
+        self.0 &= other.0;
+    }
+}
+impl BitOr for Bitboard {
+    type Output = Bitboard;
+
+    fn bitor(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 | other.0)
+    }
+}
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, other: Bitboard) {
+        self.0 |= other.0;
+    }
+}
+impl BitXor for Bitboard {
+    type Output = Bitboard;
+
+    fn bitxor(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 ^ other.0)
+    }
+}
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0 & Bitboard::ALL.0)
+    }
+}
+
+/// The directions of the slides as `(dx, dy)`, those along files and ranks first.
+const DIRECTIONS: [(i8, i8); 8] = [
+    (0, -1),
+    (0, 1),
+    (-1, 0),
+    (1, 0),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+];
+
+/// The squares from each square to the edge of the board in each direction, the square itself left out.
+static RAYS: Lazy<[[Bitboard; SQUARES]; 8]> = Lazy::new(|| {
+    let mut rays = [[Bitboard::default(); SQUARES]; 8];
+    for (d, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+        for (index, ray) in rays[d].iter_mut().enumerate() {
+            let mut coord = Bitboard::coord(index) + Coord::new(dx, dy);
+            while !coord.is_out_of_board() {
+                *ray |= Bitboard::square(coord);
+                coord = coord + Coord::new(dx, dy);
+            }
+        }
+    }
+    rays
+});
+
+/// The squares that a piece of each side, kind and promotion controls from each square by a single step.
+/// The slides of the rook, the bishop and the lance are left to `slide`.
+static STEPS: Lazy<[[[[Bitboard; SQUARES]; 2]; 8]; 2]> = Lazy::new(|| {
+    let mut steps = [[[[Bitboard::default(); SQUARES]; 2]; 8]; 2];
+    for (side, y_vector) in [(0, 1), (1, -1)] {
+        for kind in PieceKind::iter() {
+            for is_changed in [false, true] {
+                let squares = &mut steps[side][kind as usize][is_changed as usize];
+                for (index, square) in squares.iter_mut().enumerate() {
+                    for control in &CONTROL_MAP[kind][is_changed] {
+                        let coord =
+                            Bitboard::coord(index) + Coord::new(control.x, control.y * y_vector);
+                        if !coord.is_out_of_board() {
+                            *square |= Bitboard::square(coord);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    steps
+});
+
+/// The squares that a slider on `index` controls in the direction `d`, up to the first piece of `occupied`.
+fn slide(index: usize, d: usize, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[d][index];
+    let (dx, dy) = DIRECTIONS[d];
+    let blockers = ray & occupied;
+    let blocker = if dy > 0 || (dy == 0 && dx > 0) {
+        blockers.first()
+    } else {
+        blockers.last()
+    };
+    match blocker {
+        Some(blocker) => ray ^ RAYS[d][blocker],
+        None => ray,
+    }
+}
+
+fn side(status: PieceStatus) -> usize {
+    match status {
+        MyBoard | MyHand => 0,
+        EnemyBoard | EnemyHand => 1,
+    }
+}
+
+/// The number of pieces of each kind in a hand, a byte for each kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+struct Hands(u64);
+
+impl Hands {
+    fn shift(kind: PieceKind) -> u32 {
+        kind as u32 * 8
+    }
+
+    fn len(self, kind: PieceKind) -> usize {
+        (self.0 >> Hands::shift(kind) & 0xff) as usize
+    }
+
+    fn add(&mut self, kind: PieceKind) {
+        self.0 += 1 << Hands::shift(kind);
+    }
+
+    fn remove(&mut self, kind: PieceKind) {
+        self.0 -= 1 << Hands::shift(kind);
+    }
+}
+
+/// The index among the pieces of its kind of the piece on each square, and 0 for an empty square.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+struct Indices([u8; SQUARES]);
+
+impl Default for Indices {
+    fn default() -> Self {
+        Indices([0; SQUARES])
+    }
+}
+
+/// The position as bitboards: the squares of the pieces of each side and of each side and kind,
+/// those of the promoted pieces, and the hands of both sides.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub(crate) struct Bitboards {
+    occupied: [Bitboard; 2],
+    kinds: [[Bitboard; 8]; 2],
+    promoted: Bitboard,
+    hands: [Hands; 2],
+    indices: Indices,
+}
+
+impl Bitboards {
+    /// Adds the piece `i` of `kind`, which is `p`.
+    pub(crate) fn insert(&mut self, kind: PieceKind, i: usize, p: &Piece) {
+        let side = side(p.status);
+        if !p.status.on_board() {
+            self.hands[side].add(kind);
+            return;
+        }
+        let square = Bitboard::square(p.coord);
+        self.occupied[side] |= square;
+        self.kinds[side][kind as usize] |= square;
+        if p.is_changed {
+            self.promoted |= square;
+        }
+        self.indices.0[Bitboard::index(p.coord)] = i as u8;
+    }
+
+    pub(crate) fn remove(&mut self, kind: PieceKind, p: &Piece) {
+        let side = side(p.status);
+        if !p.status.on_board() {
+            self.hands[side].remove(kind);
+            return;
+        }
+        let others = !Bitboard::square(p.coord);
+        self.occupied[side] &= others;
+        self.kinds[side][kind as usize] &= others;
+        self.promoted &= others;
+        self.indices.0[Bitboard::index(p.coord)] = 0;
+    }
+
+    pub(crate) fn occupied(&self) -> Bitboard {
+        self.occupied[0] | self.occupied[1]
+    }
+
+    /// The status and the kind of the piece on `coord`, if any.
+    pub(crate) fn at(&self, coord: Coord) -> Option<(PieceStatus, PieceKind)> {
+        let (side, status) = if self.occupied[0].contains(coord) {
+            (0, MyBoard)
+        } else if self.occupied[1].contains(coord) {
+            (1, EnemyBoard)
+        } else {
+            return None;
+        };
+        PieceKind::iter()
+            .find(|&kind| self.kinds[side][kind as usize].contains(coord))
+            .map(|kind| (status, kind))
+    }
+
+    /// The index among the pieces of its kind of the piece on `coord`, which must not be empty.
+    pub(crate) fn index_at(&self, coord: Coord) -> usize {
+        self.indices.0[Bitboard::index(coord)] as usize
+    }
+
+    /// The squares of the pieces of `kind` of `status` that are promoted or not, as `is_changed`.
+    pub(crate) fn pieces(
+        &self,
+        status: PieceStatus,
+        kind: PieceKind,
+        is_changed: bool,
+    ) -> Bitboard {
+        let promoted = if is_changed {
+            self.promoted
+        } else {
+            !self.promoted
+        };
+        self.kinds[side(status)][kind as usize] & promoted
+    }
+
+    /// Number of pieces of `kind` in the hand of the side of `status`.
+    pub(crate) fn hand(&self, status: PieceStatus, kind: PieceKind) -> usize {
+        self.hands[side(status)].len(kind)
+    }
+
+    /// Whether the pieces on the board are the same, whatever the hands.
+    pub(crate) fn same_squares(&self, other: &Bitboards) -> bool {
+        self.kinds == other.kinds && self.promoted == other.promoted
+    }
+
+    /// The squares of the pieces of `status` on the board that control `target`.
+    pub(crate) fn attackers(&self, target: Coord, status: PieceStatus) -> Bitboard {
+        self.attackers_through(target, status, self.occupied())
+    }
+
+    /// Same as `attackers`, but the pieces slide as if the squares of `occupied` were the occupied ones,
+    /// which tells the controls after a move without making it.
+    pub(crate) fn attackers_through(
+        &self,
+        target: Coord,
+        status: PieceStatus,
+        occupied: Bitboard,
+    ) -> Bitboard {
+        let side = side(status);
+        let index = Bitboard::index(target);
+        // The steps are the same on both sides of a file, so a piece controls `target` from the squares
+        // that the same piece of the other side controls from `target`.
+        let steps = &STEPS[1 - side];
+        let mut attackers = Bitboard::default();
+        for kind in PieceKind::iter() {
+            let pieces = self.kinds[side][kind as usize];
+            if pieces.is_empty() {
+                continue;
+            }
+            let [normal, changed] = &steps[kind as usize];
+            attackers |= normal[index] & pieces & !self.promoted;
+            attackers |= changed[index] & pieces & self.promoted;
+        }
+
+        let mut slides = |directions: std::ops::Range<usize>, pieces: Bitboard| {
+            if !pieces.is_empty() {
+                for d in directions {
+                    attackers |= slide(index, d, occupied) & pieces;
+                }
+            }
+        };
+        slides(0..4, self.kinds[side][Hisha as usize]);
+        slides(4..8, self.kinds[side][Kaku as usize]);
+        // A lance slides forward, so it controls `target` from behind it as seen by its side.
+        let behind = if side == 0 { 1 } else { 0 };
+        slides(behind..behind + 1, self.pieces(status, Kyousha, false));
+        attackers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Board;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_slide() {
+        let occupied = Bitboard::square(Coord::new(4, 2)) | Bitboard::square(Coord::new(4, 7));
+        let up = slide(Bitboard::index(Coord::new(4, 4)), 0, occupied);
+        assert_eq!(
+            up.iter().collect::<Vec<_>>(),
+            [Coord::new(4, 2), Coord::new(4, 3)]
+        );
+        let down = slide(Bitboard::index(Coord::new(4, 4)), 1, occupied);
+        assert_eq!(
+            down,
+            Bitboard::square(Coord::new(4, 5))
+                | Bitboard::square(Coord::new(4, 6))
+                | Bitboard::square(Coord::new(4, 7))
+        );
+        assert_eq!(
+            slide(Bitboard::index(Coord::new(0, 0)), 7, Bitboard::default())
+                .iter()
+                .count(),
+            8
+        );
+    }
+
+    /// The attackers of a square are the pieces that have a move to it, on any square but those of their own side.
+    #[test]
+    fn test_attackers() {
+        let mut n = 0;
+        for entry in std::fs::read_dir("examples").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "txt") {
+                continue;
+            }
+            let board = Board::parsed(std::fs::read_to_string(&path).unwrap()).unwrap();
+            for board in [board.reversed(), board] {
+                let Ok(children) = board.create_all_next_boards() else {
+                    continue;
+                };
+                let own = board.bitboards.occupied[0];
+                for index in 0..SQUARES {
+                    let target = Bitboard::coord(index);
+                    if own.contains(target) {
+                        continue;
+                    }
+                    let expected: HashSet<_> = children
+                        .iter()
+                        .filter(|(_, mv)| mv.to == target)
+                        .filter_map(|(_, mv)| mv.from)
+                        .collect();
+                    let actual: HashSet<_> =
+                        board.bitboards.attackers(target, MyBoard).iter().collect();
+                    assert_eq!(actual, expected, "{} {:?}", path.display(), target);
+                    n += 1;
+                }
+            }
+        }
+        assert!(n > 0, "no examples");
+    }
+}
@@ -110,9 +110,9 @@ static HISHA_VECTORS: Lazy<[Coord; 4]> = Lazy::new(|| {
 static KYOUSHA_VECTORS: Lazy<[Coord; 1]> = Lazy::new(|| [Coord::new(0, -1)]);
 pub(crate) fn get_vectors(kind: PieceKind) -> &'static [Coord] {
     match kind {
-        PieceKind::Kaku => &KAKU_VECTORS.as_ref(),
-        PieceKind::Hisha => &HISHA_VECTORS.as_ref(),
-        PieceKind::Kyousha => &KYOUSHA_VECTORS.as_ref(),
+        PieceKind::Kaku => KAKU_VECTORS.as_ref(),
+        PieceKind::Hisha => HISHA_VECTORS.as_ref(),
+        PieceKind::Kyousha => KYOUSHA_VECTORS.as_ref(),
         _ => panic!("Invalid kind: {:?}", kind),
     }
 }
@@ -1,21 +1,17 @@
 use std::collections::HashSet;
 
-use super::{get_vectors, Board, Coord, PieceKind, PieceStatus, BOARD_SIZE, CONTROL_MAP};
+use super::{
+    get_vectors, Bitboard, Board, Coord, Move, PieceKind, PieceStatus, BOARD_SIZE, CONTROL_MAP,
+};
 use crate::{Error, Piece, Result};
-use bincode::{Decode, Encode};
 use PieceKind::*;
 use PieceStatus::*;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Encode, Decode)]
-pub(crate) enum NextBoardKind {
-    Normal,
-    Uchifu,
-}
-
 impl Board {
-    fn append_moved_boards(
+    fn append_moves(
         &self,
-        boards: &mut Vec<(Board, NextBoardKind)>,
+        moves: &mut Vec<Move>,
+        accepts: &dyn Fn(&Move) -> bool,
         kind: PieceKind,
         i: usize,
         p: &Piece,
@@ -33,18 +29,13 @@ impl Board {
                 let mut cloned = self.clone();
                 cloned[kind][i] = Piece::moved(new_coord, p.is_changed);
                 cloned[King][target_i] = Piece::catched(true);
-                cloned.reload_board_map();
-                return Err(Error::CatchKing(cloned));
+                cloned.reload_bitboards();
+                return Err(Error::CatchKing(Box::new(cloned)));
             }
             if self[target_kind][target_i].status == MyBoard {
                 return Ok(false);
             }
         }
-        let catch_if_needed = |board: &mut Board| {
-            if let Some((_, k, i)) = target_piece_three {
-                board[k][i] = Piece::catched(true);
-            }
-        };
 
         let (is_changable, is_force_change) = if p.is_changed {
             (false, false)
@@ -66,27 +57,32 @@ impl Board {
                 Kin | King => (false, false),
             }
         };
-        if is_changable || is_force_change {
-            let mut cloned = self.clone();
-            cloned[kind][i] = Piece::moved(new_coord, true);
-            catch_if_needed(&mut cloned);
-            cloned.reload_board_map();
-            boards.push((cloned, NextBoardKind::Normal));
+        let capture = target_piece_three.map(|(_, k, _)| k);
+        let mv = |promote: bool| {
+            Move::moved(
+                p.coord,
+                new_coord,
+                kind,
+                p.is_changed,
+                promote,
+                is_changable || is_force_change,
+                capture,
+            )
+        };
+        if (is_changable || is_force_change) && accepts(&mv(true)) {
+            moves.push(mv(true));
         }
-        if !is_force_change {
-            let mut cloned = self.clone();
-            cloned[kind][i] = Piece::moved(new_coord, p.is_changed);
-            catch_if_needed(&mut cloned);
-            cloned.reload_board_map();
-            boards.push((cloned, NextBoardKind::Normal));
+        if !is_force_change && accepts(&mv(false)) {
+            moves.push(mv(false));
         }
 
-        Ok(!target_piece_three.is_some())
+        Ok(target_piece_three.is_none())
     }
 
-    fn append_vector_moved_boards(
+    fn append_vector_moves(
         &self,
-        boards: &mut Vec<(Board, NextBoardKind)>,
+        moves: &mut Vec<Move>,
+        accepts: &dyn Fn(&Move) -> bool,
         kind: PieceKind,
         i: usize,
         vectors: &[Coord],
@@ -94,7 +90,7 @@ impl Board {
         let p = &self[kind][i];
         for vector in vectors {
             for n in 1..=(BOARD_SIZE as i8 - 1) {
-                if !self.append_moved_boards(boards, kind, i, p, *vector * n)? {
+                if !self.append_moves(moves, accepts, kind, i, p, *vector * n)? {
                     break;
                 }
             }
@@ -102,89 +98,157 @@ impl Board {
         Ok(())
     }
 
-    fn append_put_boards(
+    fn append_puts(
         &self,
-        boards: &mut Vec<(Board, NextBoardKind)>,
+        moves: &mut Vec<Move>,
+        accepts: &dyn Fn(&Move) -> bool,
         kind: PieceKind,
-        i: usize,
-        empty_cells: &Vec<Coord>,
+        empty_cells: &[Coord],
     ) {
-        let mut put = |empty_coord: Coord, next_board_kind: NextBoardKind| {
-            let mut cloned = self.clone();
-            cloned[kind][i] = Piece::moved(empty_coord, false);
-            cloned.reload_board_map();
-            boards.push((cloned, next_board_kind));
+        let pawns = self.bitboards.pieces(MyBoard, Fu, false);
+        let puts = empty_cells
+            .iter()
+            .filter(|empty_coord| match kind {
+                Fu => empty_coord.y > 0 && (pawns & Bitboard::file(empty_coord.x)).is_empty(),
+                Kyousha => empty_coord.y > 0,
+                Keima => empty_coord.y > 1,
+                _ => true,
+            })
+            .map(|&empty_coord| Move::put(empty_coord, kind))
+            .filter(|mv| accepts(mv));
+        moves.extend(puts);
+    }
+
+    /// Whether the pawn just dropped on `pawn` of this board mates the enemy, which is forbidden (打ち歩詰め).
+    /// The escapes of the king and the captures of the pawn are told from the controls, without making them.
+    fn is_uchifuzume(&self, pawn: Coord) -> bool {
+        // If my king is in check, the enemy takes it, so the drop is illegal anyway.
+        if !self.is_checking() || self.is_checked() {
+            return false;
+        }
+        let Some(king) = self[King].iter().find(|p| p.status == EnemyBoard) else {
+            return false;
         };
-        match kind {
-            Fu => {
-                for empty_coord in empty_cells.iter() {
-                    if empty_coord.y == 0 {
-                        continue;
-                    }
-                    let mut is_nifu = false;
-                    for y in 0..9 {
-                        let Some((p, kind, _)) = self.piece_at(&Coord::new(empty_coord.x, y))
-                        else {
-                            continue;
-                        };
-                        if kind == Fu && p.status == MyBoard && !p.is_changed {
-                            is_nifu = true;
-                            break;
-                        }
-                    }
-                    if is_nifu {
-                        continue;
-                    }
-                    put(*empty_coord, NextBoardKind::Uchifu);
-                }
-            }
-            Kyousha => {
-                for empty_coord in empty_cells.iter() {
-                    if empty_coord.y == 0 {
-                        continue;
-                    }
-                    put(*empty_coord, NextBoardKind::Normal);
-                }
-            }
-            Keima => {
-                for empty_coord in empty_cells.iter() {
-                    if empty_coord.y <= 1 {
-                        continue;
+        let king = king.coord;
+        let occupied = self.bitboards.occupied();
+
+        // The pawn checks from next to the king, where no piece can be dropped in between.
+        let without_king = occupied & !Bitboard::square(king);
+        let escapes = CONTROL_MAP[King][false].iter().any(|&vec| {
+            let to = king + vec;
+            !to.is_out_of_board()
+                && !matches!(self.bitboards.at(to), Some((EnemyBoard, _)))
+                && self
+                    .bitboards
+                    .attackers_through(to, MyBoard, without_king)
+                    .is_empty()
+        });
+        if escapes {
+            return false;
+        }
+        let defenders = self.attackers(pawn, EnemyBoard) & !Bitboard::square(king);
+        let takes = defenders.iter().any(|defender| {
+            let occupied = occupied & !Bitboard::square(defender);
+            let checkers = self.bitboards.attackers_through(king, MyBoard, occupied);
+            (checkers & !Bitboard::square(pawn)).is_empty()
+        });
+        !takes
+    }
+
+    /// Returns the boards after all the moves, including those that leave the king in check,
+    /// but not the drops of a pawn that mate.
+    pub(crate) fn create_all_next_boards(&self) -> Result<Vec<(Board, Move)>> {
+        self.create_next_boards(&|_| true, &|_| true)
+    }
+
+    /// Returns the boards after the legal moves that check the enemy king, in the same order as
+    /// `create_all_next_boards`. The moves that cannot check are left out before their boards are made.
+    pub(crate) fn create_checking_boards(&self) -> Result<Vec<(Board, Move)>> {
+        let Some(king) = self[King].iter().find(|p| p.status == EnemyBoard) else {
+            return Ok(Vec::new());
+        };
+        let king = king.coord;
+        self.create_next_boards(&|mv| mv.may_check(king), &|board| {
+            !board.is_checked() && board.is_checking()
+        })
+    }
+
+    /// Returns the boards after the legal moves that get the king out of check, in the same order as
+    /// `create_all_next_boards`: those of the king, and the others that take the checker or come in between.
+    pub(crate) fn create_evading_boards(&self) -> Result<Vec<(Board, Move)>> {
+        let targets = self.evasion_targets();
+        let accepts = |mv: &Move| {
+            targets.as_ref().is_none_or(|targets| {
+                (mv.kind == King && !mv.is_drop()) || targets.contains(&mv.to)
+            })
+        };
+        self.create_next_boards(&accepts, &|board| !board.is_checked())
+    }
+
+    /// The squares where a piece but the king must move to get the king out of check: that of the checker
+    /// and those between it and the king, or none against two checkers. `None` if the king is not in check.
+    fn evasion_targets(&self) -> Option<Vec<Coord>> {
+        let king = self[King].iter().find(|p| p.status == MyBoard)?;
+        let checkers: Vec<_> = self.attackers(king.coord, EnemyBoard).iter().collect();
+        match checkers[..] {
+            [] => None,
+            [checker] => {
+                let mut targets = vec![checker];
+                let (dx, dy) = (king.coord.x - checker.x, king.coord.y - checker.y);
+                if dx == 0 || dy == 0 || dx.abs() == dy.abs() {
+                    let step = Coord::new(dx.signum(), dy.signum());
+                    let mut coord = checker + step;
+                    while coord != king.coord {
+                        targets.push(coord);
+                        coord = coord + step;
                     }
-                    put(*empty_coord, NextBoardKind::Normal);
-                }
-            }
-            _ => {
-                for empty_coord in empty_cells.iter() {
-                    put(*empty_coord, NextBoardKind::Normal);
                 }
+                Some(targets)
             }
+            _ => Some(Vec::new()),
         }
     }
 
-    pub(crate) fn create_all_next_boards(&self) -> Result<Vec<(Board, NextBoardKind)>> {
+    /// Returns the boards after the moves that `accepts` that `keeps` after they are made on this board,
+    /// but not the drops of a pawn that mate. Only the boards kept are cloned.
+    fn create_next_boards(
+        &self,
+        accepts: &dyn Fn(&Move) -> bool,
+        keeps: &dyn Fn(&Board) -> bool,
+    ) -> Result<Vec<(Board, Move)>> {
+        let moves = self.next_moves(true, accepts)?;
+        let mut board = self.clone();
         let mut boards = Vec::new();
-        let empty_cells = (0..BOARD_SIZE)
-            .flat_map(|y| {
-                (0..BOARD_SIZE)
-                    .filter(move |x: &usize| self.board_map[y][*x].is_none())
-                    .map(move |x| Coord::new(x as i8, y as i8))
-            })
-            .collect();
+        for mv in moves {
+            let undo = board.do_move(&mv);
+            let is_uchifuzume = mv.is_drop() && mv.kind == Fu && board.is_uchifuzume(mv.to);
+            if !is_uchifuzume && keeps(&board) {
+                boards.push((board.clone(), mv));
+            }
+            board.undo_move(undo);
+        }
+        Ok(boards)
+    }
+
+    /// The moves that `accepts`, including those that leave the king in check and the drops of a pawn
+    /// that mate, in the order of the boards of `create_all_next_boards`.
+    fn next_moves(&self, with_drops: bool, accepts: &dyn Fn(&Move) -> bool) -> Result<Vec<Move>> {
+        let mut moves = Vec::new();
+        let empty_cells: Vec<_> = (!self.bitboards.occupied()).iter().collect();
 
         let mut put_kinds = HashSet::new();
         for (k, i, p) in self.pieces.iter() {
             match p.status {
                 MyBoard => {
                     for vec in &CONTROL_MAP[k][p.is_changed] {
-                        self.append_moved_boards(&mut boards, k, i, p, *vec)?;
+                        self.append_moves(&mut moves, accepts, k, i, p, *vec)?;
                     }
                 }
-                MyHand => {
+                MyHand if with_drops => {
                     if put_kinds.contains(&k) {
                         continue;
                     }
-                    self.append_put_boards(&mut boards, k, i, &empty_cells);
+                    self.append_puts(&mut moves, accepts, k, &empty_cells);
                     put_kinds.insert(k);
                 }
                 _ => {}
@@ -196,16 +260,16 @@ impl Board {
                 if p.status != MyBoard {
                     continue;
                 }
-                self.append_vector_moved_boards(&mut boards, kind, i, get_vectors(kind))?;
+                self.append_vector_moves(&mut moves, accepts, kind, i, get_vectors(kind))?;
             }
         }
         for (i, p) in self.pieces[Kyousha].iter().enumerate() {
             if p.status != MyBoard || p.is_changed {
                 continue;
             }
-            self.append_vector_moved_boards(&mut boards, Kyousha, i, get_vectors(Kyousha))?;
+            self.append_vector_moves(&mut moves, accepts, Kyousha, i, get_vectors(Kyousha))?;
         }
 
-        return Ok(boards);
+        Ok(moves)
     }
 }
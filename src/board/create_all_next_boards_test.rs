@@ -1,10 +1,14 @@
 #[cfg(test)]
 mod tests {
     use crate::{
-        board::assert_eq_board, Board, Coord, Piece, PieceKind::*, PieceStatus::*, Result,
+        board::assert_eq_board, Board, Coord, Move, Piece, PieceKind::*, PieceStatus::*, Result,
     };
 
-    use std::collections::HashSet;
+    use std::{
+        collections::HashSet,
+        fs::{read_dir, read_to_string},
+        path::Path,
+    };
 
     fn assert_eq_boards<T, I>(left: Vec<(Board, T)>, right: I)
     where
@@ -29,7 +33,7 @@ mod tests {
     fn test_create_all_next_boards_king() -> Result<()> {
         let mut b = Board::all_catched();
         b[King][0] = Piece::moved(Coord::new(2, 2), false);
-        b.reload_board_map();
+        b.reload_bitboards();
         assert_eq_boards(
             b.create_all_next_boards()?,
             [
@@ -156,7 +160,7 @@ mod tests {
         let mut b = Board::all_catched();
         b[Keima][0] = Piece::catched(true);
         println!("{}", b);
-        b.reload_board_map();
+        b.reload_bitboards();
         assert_eq!(b.create_all_next_boards()?.len(), 63);
 
         Ok(())
@@ -167,7 +171,7 @@ mod tests {
         let mut b = Board::all_catched();
         b[Fu][0] = Piece::moved(Coord::new(0, 2), false);
         b[Fu][1] = Piece::moved(Coord::new(1, 2), false);
-        b.reload_board_map();
+        b.reload_bitboards();
         assert_eq_boards(
             b.create_all_next_boards()?,
             [
@@ -210,7 +214,7 @@ mod tests {
         let mut b = Board::all_catched();
         b[Fu][0] = Piece::moved(Coord::new(0, 1), false);
         b[Fu][1] = Piece::moved(Coord::new(1, 1), false);
-        b.reload_board_map();
+        b.reload_bitboards();
         assert_eq_boards(
             b.create_all_next_boards()?,
             [
@@ -262,7 +266,7 @@ mod tests {
         b[Fu][8] = Piece::moved(Coord::new(8, 1), true);
         b[Fu][9] = Piece::init(8, 0, EnemyBoard);
         b[Fu][10] = Piece::catched(true);
-        b.reload_board_map();
+        b.reload_bitboards();
 
         assert_eq_board(
             &b,
@@ -561,7 +565,7 @@ mod tests {
         let mut b = Board::all_catched();
         b[Hisha][0] = Piece::moved(Coord::new(1, 1), false);
         b[Hisha][1] = Piece::moved(Coord::new(1, 3), true);
-        b.reload_board_map();
+        b.reload_bitboards();
         assert_eq_board(
             &b,
             "
@@ -979,4 +983,137 @@ mod tests {
 
         Ok(())
     }
+
+    fn drops_fu(b: &Board, x: i8, y: i8) -> Result<bool> {
+        Ok(b.create_all_next_boards()?
+            .iter()
+            .any(|(_, mv)| mv.is_drop() && mv.kind == Fu && mv.to == Coord::new(x, y)))
+    }
+
+    #[test]
+    fn test_create_all_next_boards_uchifuzume() -> Result<()> {
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Fu][0] = Piece::catched(true);
+        b[Hisha][0] = Piece::moved(Coord::new(7, 2), true);
+        b.reload_bitboards();
+        assert!(!drops_fu(&b, 8, 1)?);
+
+        // The king takes the pawn when the rook does not protect it.
+        b[Hisha][0] = Piece::moved(Coord::new(7, 2), false);
+        b.reload_bitboards();
+        assert!(drops_fu(&b, 8, 1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_all_next_boards_uchifuzume_pinned() -> Result<()> {
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Gin][0] = Piece::new(7, 0, EnemyBoard, false);
+        b[Kin][0] = Piece::moved(Coord::new(7, 2), false);
+        b[Fu][0] = Piece::catched(true);
+        b.reload_bitboards();
+        // The silver takes the pawn.
+        assert!(drops_fu(&b, 8, 1)?);
+
+        // The silver cannot take it when the rook pins it to the king.
+        b[Hisha][0] = Piece::moved(Coord::new(0, 0), false);
+        b.reload_bitboards();
+        assert!(!drops_fu(&b, 8, 1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_all_next_boards_uchifuzume_distant() -> Result<()> {
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Kin][0] = Piece::moved(Coord::new(6, 1), false);
+        b[Kaku][0] = Piece::moved(Coord::new(4, 5), false);
+        b[Fu][0] = Piece::catched(true);
+        b.reload_bitboards();
+        // The bishop protects the pawn from afar.
+        assert!(!drops_fu(&b, 8, 1)?);
+
+        // The king takes the pawn when a piece blocks the bishop.
+        b[Fu][1] = Piece::new(6, 3, EnemyBoard, false);
+        b.reload_bitboards();
+        assert!(drops_fu(&b, 8, 1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_all_next_boards_uchifuzume_defender() -> Result<()> {
+        // The defender must not mate the attacker by dropping a pawn either.
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Hisha][0] = Piece::new(1, 6, EnemyBoard, true);
+        b.reload_bitboards();
+        let defender = b.reversed();
+        assert!(!drops_fu(&defender, 8, 1)?);
+        assert!(drops_fu(&defender, 8, 2)?);
+
+        Ok(())
+    }
+
+    /// The moves of `generated` must be those of `create_all_next_boards` on `board` that `is_valid` keeps.
+    fn assert_same_moves(
+        board: &Board,
+        generated: &[(Board, Move)],
+        is_valid: impl Fn(&Board) -> bool,
+    ) -> Result<()> {
+        let expected: Vec<_> = board
+            .create_all_next_boards()?
+            .into_iter()
+            .filter(|(b, _)| is_valid(b))
+            .map(|(_, mv)| mv)
+            .collect();
+        let moves: Vec<_> = generated.iter().map(|(_, mv)| *mv).collect();
+        assert_eq!(moves, expected, "\n{}", board);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checking_and_evading_boards() -> Result<()> {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut n = 0;
+        for entry in read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "txt") {
+                continue;
+            }
+            let body = read_to_string(&path).unwrap();
+            let board = if body.contains("の持駒：") {
+                Board::from_bod(body)
+            } else {
+                Board::parsed(body)
+            }
+            .unwrap();
+            n += 1;
+
+            // The checks of the attacker, the evasions from them and the checks after those.
+            let checks = board.create_checking_boards()?;
+            assert_same_moves(&board, &checks, |b| !b.is_checked() && b.is_checking())?;
+            for (check, _) in &checks {
+                let defender = check.reversed();
+                let evasions = defender.create_evading_boards()?;
+                assert_same_moves(&defender, &evasions, |b| !b.is_checked())?;
+                for (evasion, _) in &evasions {
+                    let attacker = evasion.reversed();
+                    let checks = attacker.create_checking_boards()?;
+                    assert_same_moves(&attacker, &checks, |b| !b.is_checked() && b.is_checking())?;
+                }
+            }
+        }
+        assert!(n > 0, "no examples");
+
+        Ok(())
+    }
 }
@@ -0,0 +1,85 @@
+use super::{Board, Move, Piece, PieceKind, PieceStatus::*};
+
+/// What `undo_move` needs to take a move back: the pieces that it changed as they were before, and the hash.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Undo {
+    moved: (PieceKind, usize, Piece),
+    captured: Option<(PieceKind, usize, Piece)>,
+    hash: u64,
+}
+
+impl Board {
+    /// Plays `mv` on this board in place, which stays seen from the side that played it.
+    /// The bitboards, the hands and the hash are updated incrementally.
+    pub(crate) fn do_move(&mut self, mv: &Move) -> Undo {
+        let hash = self.hash;
+        match mv.from {
+            Some(from) => {
+                let (_, kind, i) = self.piece_at(&from).expect("no piece to move");
+                let moved = (kind, i, self[kind][i]);
+                let captured = self.piece_at(&mv.to).map(|(&p, kind, i)| (kind, i, p));
+                self.move_piece(kind, i, mv.to, mv.is_changed || mv.promote);
+                Undo {
+                    moved,
+                    captured,
+                    hash,
+                }
+            }
+            None => {
+                let i = self[mv.kind]
+                    .iter()
+                    .position(|p| p.status == MyHand)
+                    .expect("no piece to put");
+                let moved = (mv.kind, i, self[mv.kind][i]);
+                self.put_piece(mv.kind, i, mv.to);
+                Undo {
+                    moved,
+                    captured: None,
+                    hash,
+                }
+            }
+        }
+    }
+
+    /// Takes back the move that returned `undo`, which must be the last one played on this board.
+    pub(crate) fn undo_move(&mut self, undo: Undo) {
+        // The moved piece first, as the captured one goes back to its square.
+        for (kind, i, p) in [Some(undo.moved), undo.captured].into_iter().flatten() {
+            let current = self[kind][i];
+            self.bitboards.remove(kind, &current);
+            self[kind][i] = p;
+            self.bitboards.insert(kind, i, &p);
+        }
+        self.hash = undo.hash;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Board;
+
+    #[test]
+    fn test_do_move() {
+        let board = Board::parsed(include_str!("../../examples/nine.txt")).unwrap();
+        let mut n = 0;
+        for board in [board.reversed(), board] {
+            let Ok(children) = board.create_all_next_boards() else {
+                continue;
+            };
+            let mut played = board.clone();
+            for (_, mv) in children {
+                let undo = played.do_move(&mv);
+                let mut reloaded = played.clone();
+                reloaded.reload_bitboards();
+                assert_eq!(played.bitboards, reloaded.bitboards);
+                assert_eq!(played.hash, reloaded.hash);
+                played.undo_move(undo);
+                assert_eq!(played, board);
+                assert_eq!(played.bitboards, board.bitboards);
+                assert_eq!(played.hash, board.hash);
+                n += 1;
+            }
+        }
+        assert!(n > 0);
+    }
+}
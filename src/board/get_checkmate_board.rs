@@ -1,32 +1,200 @@
+mod checkpoint;
+mod hand;
 mod node;
+mod progress;
+mod solver;
+mod table;
+mod verification;
 
-use super::Board;
-use crate::db;
-use node::NormalNode;
-use std::{collections::HashSet, path::Path};
+use super::{Board, Move, MoveTree};
+use crate::db::{self, StorageConfig};
+pub use checkpoint::{Checkpoint, MismatchReason};
+use node::{NormalNode, PnDn};
+pub use progress::{Progress, ProgressObserver};
+use solver::Solver;
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Instant,
+};
+use table::{MemoryTable, Table};
+pub use verification::{AlternativeMate, Verification};
 
 pub enum CheckmateResult<T> {
     Checkmate(T, usize),
     NotCheckmate(T, usize),
-    Unproven,
+    /// The search ended by a limit of `SearchOptions` after the number of searches.
+    Unproven(usize),
+}
+
+/// Limits of a search. The default searches until checkmate or not checkmate is proven.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Number of node expansions.
+    pub num_searches: Option<usize>,
+    /// Max depth of the search, counting the root and the leaf.
+    pub max_depth: Option<usize>,
+    pub deadline: Option<Instant>,
+    /// Set it from another thread to abort the search.
+    pub stop: Option<Arc<AtomicBool>>,
+    pub progress: Option<ProgressObserver>,
+    /// Keep the positions in a fixed-size table of about this many bytes in memory
+    /// instead of the database, which is then not opened. Positions are dropped when it is full.
+    pub table_size: Option<usize>,
+    /// Continue the search saved in the database, which is then not reset, if its checkpoint `matches`
+    /// the board and the options. The count of searches goes on from the checkpoint.
+    /// Otherwise the database is reset as asked, and the search starts over.
+    pub resume: bool,
+    /// Save a checkpoint every this many node expansions. One is saved at the end of the search anyway.
+    pub checkpoint_interval: Option<usize>,
+    pub storage: StorageConfig,
+    /// Number of threads searching together through the table. The calling thread is the main one,
+    /// which alone counts `num_searches`, reports progress and saves checkpoints.
+    pub threads: usize,
+    /// Also leave out of the answer the defender's pieces dropped between the king and a distant checker
+    /// where nothing can take the checker back, once taking them is found to mate, even if the piece
+    /// would help the defender given back. The search itself is not changed, as a drop that only looks
+    /// useless may be a defense.
+    pub exclude_useless_interpositions: bool,
+}
+
+/// What a worker thread of a search shares with the nodes it visits.
+pub(crate) struct Search<'a> {
+    pub(crate) table: &'a dyn Table,
+    pub(crate) options: &'a SearchOptions,
+    /// 0 for the main thread. The others visit children in their own order to share different results.
+    pub(crate) worker: usize,
+    /// Set when another thread ends the search, which then stops as on its own options.
+    pub(crate) done: Option<&'a AtomicBool>,
+}
+
+impl Search<'_> {
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.options.is_interrupted() || self.done.is_some_and(|done| done.load(Ordering::Relaxed))
+    }
+}
+
+impl SearchOptions {
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+            || self
+                .stop
+                .as_ref()
+                .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    fn open_table<P>(&self, board: &Board, db_path: P, reset_db: bool) -> Box<dyn Table>
+    where
+        P: AsRef<Path>,
+    {
+        let open = |reset_db| {
+            db::open(&db_path, reset_db, &self.storage)
+                .unwrap_or_else(|e| panic!("failed to open the database: {e}"))
+        };
+        match self.table_size {
+            Some(size) => Box::new(MemoryTable::new(size)),
+            None if reset_db && self.resume => {
+                let db = open(false);
+                if db
+                    .get_checkpoint()
+                    .is_some_and(|checkpoint| checkpoint.matches(board, self).is_ok())
+                {
+                    return Box::new(db);
+                }
+                // The positions of another search are not resumed.
+                drop(db);
+                Box::new(open(true))
+            }
+            None => Box::new(open(reset_db)),
+        }
+    }
 }
 
 impl<T> CheckmateResult<T> {
     pub fn is_checkmate(&self) -> bool {
-        match self {
-            CheckmateResult::Checkmate(_, _) => true,
-            _ => false,
-        }
+        matches!(self, CheckmateResult::Checkmate(_, _))
+    }
+}
+
+/// What `Board::search` found.
+struct Searched {
+    root: NormalNode,
+    /// The answer under the rules of tsume if it mates, with the attacker's other mates if asked.
+    answer: Option<(MoveTree, Vec<AlternativeMate>)>,
+    /// Number of node expansions of the main thread.
+    searches: usize,
+    /// Whether the proof of a mate was expanded to the leaves, which an interruption may prevent.
+    is_proof_complete: bool,
+}
+
+fn into_result<T, F>(searched: Searched, f: F) -> CheckmateResult<T>
+where
+    F: FnOnce(NormalNode, Option<(MoveTree, Vec<AlternativeMate>)>) -> T,
+{
+    let Searched {
+        root,
+        answer,
+        searches,
+        is_proof_complete,
+    } = searched;
+    if root.pndn.pn == 0 && is_proof_complete {
+        CheckmateResult::Checkmate(f(root, answer), searches)
+    } else if root.pndn.dn == 0 {
+        CheckmateResult::NotCheckmate(f(root, answer), searches)
+    } else {
+        CheckmateResult::Unproven(searches)
+    }
+}
+
+/// Searches the root of the main thread in another order, sharing the results through the table,
+/// until the search is interrupted or `num_searches` expansions are done. It returns the root
+/// when it resolves it, ending the search of the others.
+fn help(search: &Search, board: &Board) -> Option<NormalNode> {
+    let mut root = NormalNode::new(search.table, board.clone());
+    let mut budget = search.options.num_searches.unwrap_or(usize::MAX);
+    let threshold = PnDn::new(u32::MAX, u32::MAX);
+    root.calc_pndn(search, board, &HashSet::new(), threshold, &mut budget);
+    if !root.pndn.is_resolved() {
+        return None;
+    }
+    if let Some(done) = search.done {
+        done.store(true, Ordering::Relaxed);
+    }
+    Some(root)
+}
+
+fn into_absolute(tree: MoveTree, depth: usize) -> MoveTree {
+    let children = tree
+        .children
+        .into_iter()
+        .map(|(mv, next)| {
+            (
+                into_absolute_move(mv, depth),
+                into_absolute(next, depth + 1),
+            )
+        })
+        .collect();
+    MoveTree { children }
+}
+
+fn into_absolute_move(mv: Move, depth: usize) -> Move {
+    if depth.is_multiple_of(2) {
+        mv
+    } else {
+        mv.reversed()
     }
 }
 
 #[cfg(test)]
 impl<T> CheckmateResult<T> {
     fn is_not_checkmate(&self) -> bool {
-        match self {
-            CheckmateResult::NotCheckmate(_, _) => true,
-            _ => false,
-        }
+        matches!(self, CheckmateResult::NotCheckmate(_, _))
     }
 
     fn unwrap(self) -> T {
@@ -42,47 +210,260 @@ impl Board {
         &self,
         db_path: P,
         reset_db: bool,
-        n: Option<usize>,
-        max_depth: Option<usize>,
+        options: &SearchOptions,
     ) -> CheckmateResult<Vec<Board>>
     where
         P: AsRef<Path>,
     {
-        let db = db::open_with_cache(db_path, reset_db).unwrap();
+        let table = options.open_table(self, db_path, reset_db);
+        let searched = self.search(table.as_ref(), options, false, false);
+        into_result(searched, |root, answer| match answer {
+            Some((answer, _)) => self.boards_along(&answer.main_line()),
+            None => {
+                let mut best_boards = root.best_boards(&self.reversed());
+                best_boards.pop();
+                best_boards
+            }
+        })
+    }
 
-        let mut root = NormalNode::new(&db, self.reversed());
-        let mut i = 0;
-        loop {
-            let history = HashSet::new();
-            root.calc_pndn(&db, &history, max_depth);
-            if root.pndn.pn == 0 || root.pndn.dn == 0 {
-                break;
+    /// Same as `get_checkmate_boards`, but returns the moves from this board in the order they are played.
+    pub fn get_checkmate_moves<P>(
+        &self,
+        db_path: P,
+        reset_db: bool,
+        options: &SearchOptions,
+    ) -> CheckmateResult<Vec<Move>>
+    where
+        P: AsRef<Path>,
+    {
+        let table = options.open_table(self, db_path, reset_db);
+        let searched = self.search(table.as_ref(), options, false, false);
+        into_result(searched, |root, answer| {
+            if let Some((answer, _)) = answer {
+                return into_absolute(answer, 0).main_line();
             }
+            let mut best_moves = root.best_moves();
+            best_moves.reverse();
+            best_moves
+                .into_iter()
+                .enumerate()
+                .map(|(i, mv)| if i % 2 == 0 { mv } else { mv.reversed() })
+                .collect()
+        })
+    }
+
+    /// Same as `get_checkmate_moves`, but also keeps the defender's alternative moves if `with_variations` is set.
+    pub fn get_checkmate_tree<P>(
+        &self,
+        db_path: P,
+        reset_db: bool,
+        options: &SearchOptions,
+        with_variations: bool,
+    ) -> CheckmateResult<MoveTree>
+    where
+        P: AsRef<Path>,
+    {
+        let table = options.open_table(self, db_path, reset_db);
+        let searched = self.search(table.as_ref(), options, with_variations, false);
+        into_result(searched, |root, answer| {
+            let answer = answer.map(|(answer, _)| answer);
+            into_absolute(answer.unwrap_or_else(|| root.best_tree(with_variations)), 0)
+        })
+    }
 
-            if i % 50000 == 0 {
-                root.dump_single_best_board(&db);
-                println!("{i}");
+    /// Same as `get_checkmate_tree`, but then also tries to prove the other moves of the attacker on the main line
+    /// to tell whether the mate is unique.
+    pub fn verify_checkmate<P>(
+        &self,
+        db_path: P,
+        reset_db: bool,
+        options: &SearchOptions,
+        with_variations: bool,
+    ) -> CheckmateResult<Verification>
+    where
+        P: AsRef<Path>,
+    {
+        let table = options.open_table(self, db_path, reset_db);
+        let searched = self.search(table.as_ref(), options, with_variations, true);
+        into_result(searched, |root, answer| {
+            let (tree, alternatives) =
+                answer.unwrap_or_else(|| (root.best_tree(with_variations), Vec::new()));
+            let alternatives = alternatives
+                .into_iter()
+                .map(|alternative| AlternativeMate {
+                    mv: into_absolute_move(alternative.mv, alternative.ply),
+                    ..alternative
+                })
+                .collect();
+            Verification {
+                tree: into_absolute(tree, 0),
+                alternatives,
             }
+        })
+    }
+
+    /// The boards after each of `moves` from this board, the last first, each seen from the side that played it.
+    fn boards_along(&self, moves: &[Move]) -> Vec<Board> {
+        let mut board = self.reversed();
+        let mut boards = Vec::new();
+        for mv in moves {
+            board = board.reversed().moved(mv);
+            boards.push(board.clone());
+        }
+        boards.reverse();
+        boards
+    }
 
-            i += 1;
-            if let Some(n) = n {
-                if i == n {
-                    break;
+    /// Searches this board, and reads the answer with the defender's other moves if `with_variations` is set
+    /// and the attacker's other mates if `verify` is set.
+    fn search(
+        &self,
+        table: &dyn Table,
+        options: &SearchOptions,
+        with_variations: bool,
+        verify: bool,
+    ) -> Searched {
+        let start = Instant::now();
+        let board = self.reversed();
+        let mut i = table
+            .get_checkpoint()
+            .filter(|checkpoint| options.resume && checkpoint.matches(self, options).is_ok())
+            .map_or(0, |checkpoint| checkpoint.searches);
+        let resumed = i;
+
+        let mut root = if options.threads > 1 {
+            let done = AtomicBool::new(false);
+            // The helpers stop on the limits of the caller and when the main thread ends,
+            // but report nothing and save no checkpoint.
+            let helper_options = SearchOptions {
+                num_searches: options.num_searches,
+                max_depth: options.max_depth,
+                deadline: options.deadline,
+                stop: options.stop.clone(),
+                exclude_useless_interpositions: options.exclude_useless_interpositions,
+                ..Default::default()
+            };
+            thread::scope(|s| {
+                let helpers: Vec<_> = (1..options.threads)
+                    .map(|worker| {
+                        let search = Search {
+                            table,
+                            options: &helper_options,
+                            worker,
+                            done: Some(&done),
+                        };
+                        let board = &board;
+                        s.spawn(move || help(&search, board))
+                    })
+                    .collect();
+                let search = Search {
+                    table,
+                    options,
+                    worker: 0,
+                    done: Some(&done),
+                };
+                let main = self.search_main(&search, &board, start, &mut i, resumed);
+                done.store(true, Ordering::Relaxed);
+                // A helper that resolved the root stopped the main thread, whose count stays as it is.
+                let solved = helpers
+                    .into_iter()
+                    .filter_map(|helper| helper.join().expect("a helper panicked"))
+                    .next();
+                match solved {
+                    Some(solved) if !main.pndn.is_resolved() => solved,
+                    _ => main,
                 }
+            })
+        } else {
+            let search = Search {
+                table,
+                options,
+                worker: 0,
+                done: None,
+            };
+            self.search_main(&search, &board, start, &mut i, resumed)
+        };
+        let search = Search {
+            table,
+            options,
+            worker: 0,
+            done: None,
+        };
+        let mut answer = None;
+        let mut is_proof_complete = false;
+        if root.pndn.pn == 0 {
+            // The proof is searched again where the table dropped it, within what is left of `num_searches`.
+            let mut budget = options
+                .num_searches
+                .map_or(usize::MAX, |n| n.saturating_sub(i - resumed));
+            let before = budget;
+            is_proof_complete = root.complete_proof(&search, &board, &HashSet::new(), &mut budget);
+            if budget != before {
+                i += before - budget;
+                table.put_checkpoint(&Checkpoint::new(self, i, options));
             }
         }
+        if is_proof_complete {
+            let mut solver = Solver::new(&search);
+            answer = solver.solve(&root, &board, with_variations).map(|answer| {
+                let alternatives = if verify {
+                    solver.alternatives(&root, &board)
+                } else {
+                    Vec::new()
+                };
+                (answer, alternatives)
+            });
+        }
+        Searched {
+            root,
+            answer,
+            searches: i,
+            is_proof_complete,
+        }
+    }
 
-        if root.pndn.pn == 0 {
-            let mut best_boards = root.best_boards(&db);
-            best_boards.pop();
-            CheckmateResult::Checkmate(best_boards, i)
-        } else if root.pndn.dn == 0 {
-            let mut best_boards = root.best_boards(&db);
-            best_boards.pop();
-            CheckmateResult::NotCheckmate(best_boards, i)
-        } else {
-            CheckmateResult::Unproven
+    fn search_main(
+        &self,
+        search: &Search,
+        board: &Board,
+        start: Instant,
+        i: &mut usize,
+        resumed: usize,
+    ) -> NormalNode {
+        let Search { table, options, .. } = *search;
+        let mut root = NormalNode::new(table, board.clone());
+        loop {
+            // Return to the root every so often to stop at `num_searches`, report progress or save a checkpoint.
+            let mut budget = usize::MAX;
+            if let Some(n) = options.num_searches {
+                budget = budget.min(n - (*i - resumed));
+            }
+            if let Some(observer) = &options.progress {
+                budget = budget.min(observer.interval.max(1));
+            }
+            if let Some(interval) = options.checkpoint_interval {
+                budget = budget.min(interval.max(1));
+            }
+            let chunk = budget;
+            let history = HashSet::new();
+            let threshold = PnDn::new(u32::MAX, u32::MAX);
+            root.calc_pndn(search, board, &history, threshold, &mut budget);
+            *i += chunk - budget;
+            table.put_checkpoint(&Checkpoint::new(self, *i, options));
+
+            if root.pndn.is_resolved()
+                || search.is_interrupted()
+                || options.num_searches == Some(*i - resumed)
+            {
+                break;
+            }
+            if let Some(observer) = &options.progress {
+                let progress = Progress::new(*i, start.elapsed(), &root, board, table);
+                (observer.callback)(&progress);
+            }
         }
+        root
     }
 
     #[cfg(test)]
@@ -96,14 +477,19 @@ impl Board {
     where
         P: AsRef<Path>,
     {
-        match self.get_checkmate_boards(db_path, reset_db, Some(n), max_depth) {
+        let options = SearchOptions {
+            num_searches: Some(n),
+            max_depth,
+            ..Default::default()
+        };
+        match self.get_checkmate_boards(db_path, reset_db, &options) {
             CheckmateResult::Checkmate(mut boards, n) => {
                 CheckmateResult::Checkmate(boards.pop().unwrap(), n)
             }
             CheckmateResult::NotCheckmate(mut boards, n) => {
                 CheckmateResult::NotCheckmate(boards.pop().unwrap(), n)
             }
-            CheckmateResult::Unproven => CheckmateResult::Unproven,
+            CheckmateResult::Unproven(n) => CheckmateResult::Unproven(n),
         }
     }
 }
@@ -120,12 +506,49 @@ mod tests {
         b[King][1] = Piece::new(8, 0, EnemyBoard, false);
         b[Fu][0] = Piece::catched(true);
         b[Hisha][0] = Piece::moved(Coord::new(7, 2), true);
-        b.reload_board_map();
+        b.reload_bitboards();
         assert!(b
             .get_checkmate_board("/tmp/df_pn.test.rocksdb", true, 200, None)
             .is_not_checkmate(),);
     }
 
+    #[test]
+    fn test_refutation_line() {
+        // Only the pawn drop would mate, so the dragon checks and the king escapes.
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Fu][0] = Piece::catched(true);
+        b[Hisha][0] = Piece::moved(Coord::new(7, 2), true);
+        b.reload_bitboards();
+        let options = SearchOptions {
+            table_size: Some(16 * 1024 * 1024),
+            ..Default::default()
+        };
+        let CheckmateResult::NotCheckmate(moves, _) = b.get_checkmate_moves("", true, &options)
+        else {
+            panic!("not refuted");
+        };
+        // The refuted check is kept with the escape of the king.
+        assert!(moves.len() >= 2, "{moves:?}");
+        assert_eq!(moves[1].kind, King);
+    }
+
+    #[test]
+    fn test_perpetual_check() {
+        // The dragon checks the king back and forth, which repeats the position.
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Hisha][0] = Piece::moved(Coord::new(7, 2), true);
+        b.reload_bitboards();
+        let options = SearchOptions {
+            table_size: Some(16 * 1024 * 1024),
+            ..Default::default()
+        };
+        assert!(b.get_checkmate_moves("", true, &options).is_not_checkmate());
+    }
+
     #[test]
     fn test_get_checkmate_board() {
         let mut b = Board::all_catched();
@@ -135,9 +558,9 @@ mod tests {
         b[Fu][0] = Piece::moved(Coord::new(4, 2), false);
         b[Kin][0] = Piece::catched(true);
         b[Hisha][0] = Piece::catched(true);
-        b.reload_board_map();
+        b.reload_bitboards();
         assert_eq_board(
-            &b.get_checkmate_board("/tmp/df_pn.test.rocksdb", true, 10, None)
+            &b.get_checkmate_board("/tmp/df_pn.test.rocksdb", true, 1000, None)
                 .unwrap(),
             "
 歩x17 香x4 桂x4 銀x4 金x3 角x2 飛
@@ -155,4 +578,330 @@ mod tests {
 飛",
         );
     }
+
+    #[test]
+    fn test_get_checkmate_moves() {
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+
+        b[King][1] = Piece::new(4, 0, EnemyBoard, false);
+        b[Fu][0] = Piece::moved(Coord::new(4, 2), false);
+        b[Kin][0] = Piece::catched(true);
+        b[Hisha][0] = Piece::catched(true);
+        b.reload_bitboards();
+        let moves = b
+            .get_checkmate_moves(
+                "/tmp/df_pn.test.moves.rocksdb",
+                true,
+                &SearchOptions {
+                    num_searches: Some(1000),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            moves.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+            ["5二金打"]
+        );
+    }
+
+    #[test]
+    fn test_verify_checkmate() {
+        let options = SearchOptions {
+            table_size: Some(64 * 1024 * 1024),
+            ..Default::default()
+        };
+        let alternatives = |b: &Board| {
+            let verification = b.verify_checkmate("", true, &options, false).unwrap();
+            let alternatives = verification
+                .alternatives
+                .iter()
+                .map(|a| (a.ply, a.mv.to_string(), a.len, a.is_finishing))
+                .collect::<Vec<_>>();
+            (verification.is_unique(), alternatives)
+        };
+
+        // The rook mates as well, but longer.
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(4, 0, EnemyBoard, false);
+        b[Fu][0] = Piece::moved(Coord::new(4, 2), false);
+        b[Kin][0] = Piece::catched(true);
+        b[Hisha][0] = Piece::catched(true);
+        b.reload_bitboards();
+        assert_eq!(
+            alternatives(&b),
+            (false, vec![(0, String::from("5二飛打"), Some(3), false)])
+        );
+
+        // Either square next to the gold mates at once.
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Kin][0] = Piece::moved(Coord::new(7, 2), false);
+        b[Kin][1] = Piece::catched(true);
+        b.reload_bitboards();
+        assert_eq!(
+            alternatives(&b),
+            (true, vec![(0, String::from("1二金打"), Some(1), true)])
+        );
+    }
+
+    #[test]
+    fn test_interrupted() {
+        let board = Board::parsed(include_str!("../../examples/nine.txt")).unwrap();
+        let options = SearchOptions {
+            stop: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        assert!(matches!(
+            board.get_checkmate_moves("/tmp/df_pn.test.interrupted.rocksdb", true, &options),
+            CheckmateResult::Unproven(0)
+        ));
+
+        let options = SearchOptions {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            board.get_checkmate_moves("/tmp/df_pn.test.interrupted.rocksdb", true, &options),
+            CheckmateResult::Unproven(0)
+        ));
+    }
+
+    #[test]
+    fn test_complete_proof() {
+        let board = Board::parsed(include_str!("../../examples/five.txt"))
+            .unwrap()
+            .reversed();
+        let table = MemoryTable::new(64 * 1024 * 1024);
+        let search = |options| Search {
+            table: &table,
+            options,
+            worker: 0,
+            done: None,
+        };
+
+        // Nothing is in the table, so the proof is searched again, which the budget cuts short.
+        let options = SearchOptions::default();
+        let mut root = NormalNode::new(&table, board.clone());
+        let mut budget = 10;
+        assert!(!root.complete_proof(&search(&options), &board, &HashSet::new(), &mut budget));
+        assert_eq!(budget, 0);
+
+        let stopped = SearchOptions {
+            stop: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        let mut budget = usize::MAX;
+        assert!(!root.complete_proof(&search(&stopped), &board, &HashSet::new(), &mut budget));
+        assert!(root.complete_proof(&search(&options), &board, &HashSet::new(), &mut budget));
+        assert_eq!(root.pndn.pn, 0);
+    }
+
+    #[test]
+    fn test_progress() {
+        let board = Board::parsed(include_str!("../../examples/five.txt")).unwrap();
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = SearchOptions {
+            num_searches: Some(10),
+            progress: Some(ProgressObserver::new(4, {
+                let calls = calls.clone();
+                move |progress| {
+                    assert!(progress.nodes() > 0);
+                    assert!(!progress.best_line().is_empty());
+                    calls.lock().unwrap().push(progress.searches);
+                }
+            })),
+            ..Default::default()
+        };
+        assert!(matches!(
+            board.get_checkmate_moves("/tmp/df_pn.test.progress.rocksdb", true, &options),
+            CheckmateResult::Unproven(10)
+        ));
+        assert_eq!(*calls.lock().unwrap(), [4, 8]);
+    }
+
+    #[test]
+    fn test_memory_table() {
+        let board = Board::parsed(include_str!("../../examples/five.txt")).unwrap();
+        let options = SearchOptions {
+            table_size: Some(64 * 1024 * 1024),
+            ..Default::default()
+        };
+        let moves = board
+            .get_checkmate_moves("/nonexistent/df_pn.rocksdb", false, &options)
+            .unwrap();
+        assert_eq!(moves.len(), 5);
+
+        // Positions are dropped and searched again, but the result is the same.
+        let options = SearchOptions {
+            table_size: Some(64 * 1024),
+            ..Default::default()
+        };
+        let small = board
+            .get_checkmate_moves("/nonexistent/df_pn.rocksdb", false, &options)
+            .unwrap();
+        assert_eq!(small.len(), 5);
+    }
+
+    #[test]
+    fn test_reuse_proof() {
+        let board = Board::parsed(include_str!("../../examples/five.txt")).unwrap();
+        let db_path = "/tmp/df_pn.test.reuse_proof.rocksdb";
+        let options = SearchOptions::default();
+        let CheckmateResult::Checkmate(moves, n) =
+            board.get_checkmate_moves(db_path, true, &options)
+        else {
+            panic!("not checkmate");
+        };
+
+        // The children of the root are resolved by the stored proofs, and the answer is read from them.
+        let CheckmateResult::Checkmate(reused, 1) =
+            board.get_checkmate_moves(db_path, false, &options)
+        else {
+            panic!("the proofs are not reused");
+        };
+        assert!(n > 1);
+        assert_eq!(reused.len(), moves.len());
+    }
+
+    #[test]
+    fn test_resume() {
+        let board = Board::parsed(include_str!("../../examples/five.txt")).unwrap();
+        let db_path = "/tmp/df_pn.test.resume.rocksdb";
+        let options = SearchOptions {
+            num_searches: Some(500),
+            checkpoint_interval: Some(100),
+            ..Default::default()
+        };
+        assert!(matches!(
+            board.get_checkmate_moves(db_path, true, &options),
+            CheckmateResult::Unproven(500)
+        ));
+        let checkpoint = Checkpoint::load(db_path).unwrap();
+        assert_eq!(checkpoint.searches, 500);
+        assert_eq!(
+            checkpoint.matches(&board, &SearchOptions::default()),
+            Ok(())
+        );
+        assert_eq!(
+            checkpoint.matches(&board.reversed(), &SearchOptions::default()),
+            Err(MismatchReason::Board)
+        );
+        let deeper = SearchOptions {
+            max_depth: Some(9),
+            ..Default::default()
+        };
+        assert_eq!(
+            checkpoint.matches(&board, &deeper),
+            Err(MismatchReason::MaxDepth(None))
+        );
+
+        let options = SearchOptions {
+            resume: true,
+            ..Default::default()
+        };
+        let CheckmateResult::Checkmate(moves, n) =
+            board.get_checkmate_moves(db_path, true, &options)
+        else {
+            panic!("not checkmate");
+        };
+        assert_eq!(moves.len(), 5);
+        assert!(n > 500);
+        assert_eq!(Checkpoint::load(db_path).unwrap().searches, n);
+
+        // The search of another board is not resumed, and the database is reset for it.
+        let other = Board::parsed(include_str!("../../examples/nine.txt")).unwrap();
+        let options = SearchOptions {
+            num_searches: Some(10),
+            resume: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            other.get_checkmate_moves(db_path, true, &options),
+            CheckmateResult::Unproven(10)
+        ));
+        let db = db::open(db_path, false, &Default::default()).unwrap();
+        assert!(Table::get(&db, &board.reversed().key()).is_none());
+    }
+
+    #[test]
+    fn test_help() {
+        let board = Board::parsed(include_str!("../../examples/five.txt"))
+            .unwrap()
+            .reversed();
+        let table = MemoryTable::new(64 * 1024 * 1024);
+        let done = AtomicBool::new(false);
+        let search = |options| Search {
+            table: &table,
+            options,
+            worker: 1,
+            done: Some(&done),
+        };
+
+        // The stop flag of the caller stops a helper as well as the main thread.
+        let options = SearchOptions {
+            stop: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        assert!(help(&search(&options), &board).is_none());
+        assert!(!done.load(Ordering::Relaxed));
+
+        // A helper resolving the root returns it and ends the search of the others.
+        let options = SearchOptions::default();
+        let root = help(&search(&options), &board).unwrap();
+        assert_eq!(root.pndn.pn, 0);
+        assert!(done.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_threads() {
+        let board = Board::parsed(include_str!("../../examples/five.txt")).unwrap();
+        let options = SearchOptions {
+            threads: 4,
+            table_size: Some(64 * 1024 * 1024),
+            ..Default::default()
+        };
+        let moves = board
+            .get_checkmate_moves("/nonexistent/df_pn.rocksdb", false, &options)
+            .unwrap();
+        assert_eq!(moves.len(), 5);
+
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Fu][0] = Piece::catched(true);
+        b[Hisha][0] = Piece::moved(Coord::new(7, 2), true);
+        b.reload_bitboards();
+        assert!(b
+            .get_checkmate_moves("/tmp/df_pn.test.threads.rocksdb", true, &options)
+            .is_not_checkmate());
+    }
+
+    #[test]
+    fn test_threads_parity() {
+        let examples = [
+            include_str!("../../examples/five.txt"),
+            include_str!("../../examples/seven.txt"),
+            include_str!("../../examples/nine.txt"),
+            include_str!("../../examples/eleven.txt"),
+        ];
+        for example in examples {
+            let board = Board::parsed(example).unwrap();
+            let solve = |threads| {
+                let options = SearchOptions {
+                    threads,
+                    table_size: Some(64 * 1024 * 1024),
+                    ..Default::default()
+                };
+                match board.get_checkmate_moves("", false, &options) {
+                    CheckmateResult::Checkmate(moves, _) => Some(moves),
+                    _ => None,
+                }
+            };
+            let moves = solve(1).unwrap();
+            assert_eq!(solve(4), Some(moves));
+        }
+    }
 }
@@ -0,0 +1,58 @@
+use super::{SearchOptions, Table};
+use crate::{db, Board};
+use bincode::{Decode, Encode};
+use std::path::Path;
+
+/// The state of a search saved in the database, from which the search can be resumed.
+/// The pn and dn of the positions are in the database as well.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct Checkpoint {
+    /// Zobrist hash of the board searched, which is the key of its position in the database.
+    pub hash: u64,
+    /// Number of node expansions so far.
+    pub searches: usize,
+    pub max_depth: Option<usize>,
+}
+
+/// Why a search cannot continue from a checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchReason {
+    /// The checkpoint is of the search of another board.
+    Board,
+    /// The checkpoint is of a search with this other `max_depth`.
+    MaxDepth(Option<usize>),
+}
+
+impl Checkpoint {
+    pub(super) fn new(board: &Board, searches: usize, options: &SearchOptions) -> Checkpoint {
+        Checkpoint {
+            hash: board.hash,
+            searches,
+            max_depth: options.max_depth,
+        }
+    }
+
+    /// Loads the checkpoint of the database at `db_path`, if any. The database must not be open.
+    pub fn load<P>(db_path: P) -> Option<Checkpoint>
+    where
+        P: AsRef<Path>,
+    {
+        if !db::is_solver_db(db_path.as_ref()) {
+            return None;
+        }
+        let db = db::open(db_path, false, &Default::default()).ok()?;
+        db.get_checkpoint()
+    }
+
+    /// Whether the search of `board` with `options` can continue from this checkpoint, which is
+    /// when the position and the options that change the pn and dn in the database are the same.
+    pub fn matches(&self, board: &Board, options: &SearchOptions) -> Result<(), MismatchReason> {
+        if self.hash != board.hash {
+            return Err(MismatchReason::Board);
+        }
+        if self.max_depth != options.max_depth {
+            return Err(MismatchReason::MaxDepth(self.max_depth));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use super::{node::Position, Table};
+use crate::{
+    board::PieceStatus::{self, *},
+    db::Key,
+    Board, Move,
+};
+use bincode::{Decode, Encode};
+
+/// Numbers of pieces in the attacker's hand, indexed by kind.
+///
+/// All the pieces not on the board nor in the attacker's hand are in the defender's, so a larger hand
+/// of the attacker is never worse for the attacker: a mate with a proof hand holds with any hand
+/// that covers it, and a disproof holds with any hand that a disproof hand covers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub(crate) struct Hand([u8; 8]);
+
+impl Hand {
+    /// A disproof hand that is not limited in any kind.
+    pub(crate) const UNLIMITED: Hand = Hand([u8::MAX; 8]);
+
+    /// The attacker's hand on the board of a node at `position`, seen from the side that made the last move.
+    pub(crate) fn attacker(board: &Board, position: Position) -> Hand {
+        match position {
+            Position::Offense => Hand::count(board, EnemyHand),
+            Position::Defense => Hand::count(board, MyHand),
+        }
+    }
+
+    pub(crate) fn defender(board: &Board, position: Position) -> Hand {
+        match position {
+            Position::Offense => Hand::count(board, MyHand),
+            Position::Defense => Hand::count(board, EnemyHand),
+        }
+    }
+
+    fn count(board: &Board, status: PieceStatus) -> Hand {
+        let mut hand = Hand::default();
+        for (kind, _, p) in board.pieces.iter() {
+            if p.status == status {
+                hand.0[kind as usize] += 1;
+            }
+        }
+        hand
+    }
+
+    /// The hand after the attacker plays `mv`.
+    pub(crate) fn after(mut self, mv: &Move) -> Hand {
+        if mv.is_drop() {
+            self.0[mv.kind as usize] -= 1;
+        }
+        if let Some(kind) = mv.capture {
+            self.0[kind as usize] += 1;
+        }
+        self
+    }
+
+    /// The proof or disproof hand before the attacker plays `mv`, from that after it.
+    pub(crate) fn before(mut self, mv: &Move) -> Hand {
+        if mv.is_drop() {
+            self.0[mv.kind as usize] = self.0[mv.kind as usize].saturating_add(1);
+        }
+        if let Some(kind) = mv.capture {
+            self.0[kind as usize] = self.0[kind as usize].saturating_sub(1);
+        }
+        self
+    }
+
+    /// Whether the hand has at least as many pieces of every kind as `other`.
+    pub(crate) fn covers(&self, other: &Hand) -> bool {
+        self.0.iter().zip(other.0).all(|(&a, b)| a >= b)
+    }
+
+    pub(crate) fn max(mut self, other: &Hand) -> Hand {
+        for (a, &b) in self.0.iter_mut().zip(&other.0) {
+            *a = (*a).max(b);
+        }
+        self
+    }
+
+    pub(crate) fn min(mut self, other: &Hand) -> Hand {
+        for (a, &b) in self.0.iter_mut().zip(&other.0) {
+            *a = (*a).min(b);
+        }
+        self
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.0.iter().map(|&n| n as u32).sum()
+    }
+
+    /// Proof hand of a defense node from the union of those of its children. Of the kinds the defender
+    /// has none, the attacker must keep all, or the defender could drop them.
+    pub(crate) fn for_defense(mut self, hand: &Hand, defender: &Hand) -> Hand {
+        for kind in 0..self.0.len() {
+            if defender.0[kind] == 0 {
+                self.0[kind] = hand.0[kind];
+            }
+        }
+        self
+    }
+
+    /// Disproof hand of an offense node from the intersection of those of its children.
+    /// The attacker must not have kinds it has none of, which it could drop.
+    pub(crate) fn for_offense(mut self, hand: &Hand) -> Hand {
+        for kind in 0..self.0.len() {
+            if hand.0[kind] == 0 {
+                self.0[kind] = 0;
+            }
+        }
+        self
+    }
+
+    /// Disproof hand of a defense node escaping with `mv`, from that after it.
+    /// A drop needs the defender to keep a piece of the kind.
+    pub(crate) fn escaping(mut self, hand: &Hand, mv: &Move) -> Hand {
+        if mv.is_drop() {
+            let kind = mv.kind as usize;
+            self.0[kind] = self.0[kind].min(hand.0[kind]);
+        }
+        self
+    }
+}
+
+/// Proof and disproof hands of the positions sharing the pieces on the board at a position,
+/// so that positions differing only in hands reuse them.
+#[derive(Clone, Debug, Encode, Decode)]
+pub(crate) struct HandEntry {
+    pub(crate) key: Key,
+    /// One of the positions, to tell hash collisions apart.
+    board: Board,
+    proofs: Vec<Hand>,
+    disproofs: Vec<Hand>,
+}
+
+/// Hands kept per entry. Hands covered by others are dropped first.
+const MAX_HANDS: usize = 16;
+
+fn hand_key(board: &Board, position: Position) -> Key {
+    let position = match position {
+        Position::Offense => 0,
+        Position::Defense => 0x5bd1_e995_5bd1_e995,
+    };
+    (board.squares_hash() ^ position).to_le_bytes()
+}
+
+fn same_squares(a: &Board, b: &Board) -> bool {
+    a.bitboards.same_squares(&b.bitboards)
+}
+
+/// Looks up whether the attacker mates at `board` of a node at `position` from the results of other hands,
+/// and returns it with the proof or disproof hand.
+pub(crate) fn lookup(table: &dyn Table, board: &Board, position: Position) -> Option<(bool, Hand)> {
+    let entry = table.get_hands(&hand_key(board, position))?;
+    if !same_squares(&entry.board, board) {
+        return None;
+    }
+    let hand = Hand::attacker(board, position);
+    if let Some(proof) = entry.proofs.iter().find(|proof| hand.covers(proof)) {
+        return Some((true, *proof));
+    }
+    let disproof = entry
+        .disproofs
+        .iter()
+        .find(|disproof| disproof.covers(&hand))?;
+    Some((false, *disproof))
+}
+
+/// Records that the attacker mates, or does not, with `hand` as the proof or disproof hand.
+pub(crate) fn record(
+    table: &dyn Table,
+    board: &Board,
+    position: Position,
+    is_checkmate: bool,
+    hand: Hand,
+) {
+    let key = hand_key(board, position);
+    let mut entry = match table.get_hands(&key) {
+        Some(entry) if same_squares(&entry.board, board) => entry,
+        _ => HandEntry {
+            key,
+            board: board.clone(),
+            proofs: Vec::new(),
+            disproofs: Vec::new(),
+        },
+    };
+    if is_checkmate {
+        if entry.proofs.iter().any(|proof| hand.covers(proof)) {
+            return;
+        }
+        entry.proofs.retain(|proof| !proof.covers(&hand));
+        entry.proofs.truncate(MAX_HANDS - 1);
+        entry.proofs.insert(0, hand);
+    } else {
+        if entry
+            .disproofs
+            .iter()
+            .any(|disproof| disproof.covers(&hand))
+        {
+            return;
+        }
+        entry.disproofs.retain(|disproof| !hand.covers(disproof));
+        entry.disproofs.truncate(MAX_HANDS - 1);
+        entry.disproofs.insert(0, hand);
+    }
+    table.put_hands(&entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::get_checkmate_board::table::MemoryTable, Coord, Piece, PieceKind::*};
+
+    #[test]
+    fn test_dominance() {
+        let mut board = Board::all_catched();
+        board[King][0] = Piece::new(4, 8, EnemyBoard, false);
+        board[King][1] = Piece::new(4, 0, MyBoard, false);
+        board[Kin][0] = Piece::new(4, 2, EnemyBoard, false);
+        board[Kin][2] = Piece::catched(true);
+        board[Kin][3] = Piece::catched(true);
+        board.reload_bitboards();
+        let hand = Hand::attacker(&board, Position::Offense);
+        assert_eq!(hand.0[Kin as usize], 1);
+        assert_eq!(hand.0[Fu as usize], 18);
+
+        let table = MemoryTable::new(1024 * 1024);
+        let mut proof = Hand::default();
+        proof.0[Kin as usize] = 1;
+        record(&table, &board, Position::Offense, true, proof);
+        assert_eq!(
+            lookup(&table, &board, Position::Offense),
+            Some((true, proof))
+        );
+        assert_eq!(lookup(&table, &board, Position::Defense), None);
+
+        // A gold less is not enough.
+        let mut weaker = board.clone();
+        weaker[Kin][1] = Piece::catched(true);
+        weaker.reload_bitboards();
+        assert_eq!(lookup(&table, &weaker, Position::Offense), None);
+        let disproof = Hand::attacker(&weaker, Position::Offense);
+        record(&table, &weaker, Position::Offense, false, disproof);
+        assert_eq!(
+            lookup(&table, &weaker, Position::Offense),
+            Some((false, disproof))
+        );
+        assert_eq!(
+            lookup(&table, &board, Position::Offense),
+            Some((true, proof))
+        );
+
+        // Another square is another entry.
+        let mut moved = board.clone();
+        moved[Kin][0] = Piece::moved(Coord::new(3, 6), false);
+        moved.reload_bitboards();
+        assert_eq!(lookup(&table, &moved, Position::Offense), None);
+    }
+}
@@ -1,16 +1,16 @@
+mod dependency;
 mod force_not_checkmate_node;
 mod multi_set;
 mod normal_node;
 mod pndn;
 
-use crate::{
-    db::{Key, DB},
-    Board,
-};
+use super::{hand::Hand, Search, Table};
+use crate::{db::Key, Board, Move, MoveTree};
+pub(super) use dependency::Dependency;
 use force_not_checkmate_node::ForceNotCheckmateNode;
 use multi_set::*;
-pub(super) use normal_node::NormalNode;
-use pndn::*;
+pub(super) use normal_node::{BoardNode, Child, NormalNode, Proof};
+pub(super) use pndn::PnDn;
 use std::collections::HashSet;
 
 pub(super) enum Node {
@@ -18,11 +18,73 @@ pub(super) enum Node {
     Normal(NormalNode),
 }
 
+/// The methods taking `parent` expect the board of the parent node reversed,
+/// that is, seen from the side that plays the move to this node.
 impl Node {
-    pub(super) fn calc_pndn(&mut self, db: &DB, history: &HashSet<Key>, max_depth: Option<usize>) {
+    pub(super) fn calc_pndn(
+        &mut self,
+        search: &Search,
+        parent: &Board,
+        history: &HashSet<Key>,
+        threshold: PnDn,
+        budget: &mut usize,
+    ) {
         match self {
             Node::ForceNotCheckmate(_) => {}
-            Node::Normal(node) => node.calc_pndn(db, history, max_depth),
+            Node::Normal(node) => {
+                let board = node.board(parent);
+                node.calc_pndn(search, &board, history, threshold, budget)
+            }
+        }
+    }
+
+    pub(super) fn complete_proof(
+        &mut self,
+        search: &Search,
+        parent: &Board,
+        history: &HashSet<Key>,
+        budget: &mut usize,
+    ) -> bool {
+        match self {
+            Node::ForceNotCheckmate(_) => false,
+            Node::Normal(node) => {
+                let board = node.board(parent);
+                node.complete_proof(search, &board, history, budget)
+            }
+        }
+    }
+
+    fn is_expanded(&self) -> bool {
+        match self {
+            Node::ForceNotCheckmate(_) => false,
+            Node::Normal(node) => node.is_expanded(),
+        }
+    }
+
+    /// A forced result is due to a repetition or the depth limit, which depend on the path.
+    fn dependency(&self) -> &Dependency {
+        match self {
+            Node::ForceNotCheckmate(node) => &node.dependency,
+            Node::Normal(node) => node.dependency(),
+        }
+    }
+
+    fn is_path_dependent(&self) -> bool {
+        self.dependency().is_path_dependent()
+    }
+
+    /// The proof or disproof hand of a resolved node, which is at worst the hand of the attacker.
+    fn result_hand(&self) -> Option<Hand> {
+        match self {
+            Node::ForceNotCheckmate(_) => None,
+            Node::Normal(node) => Some(node.result_hand.unwrap_or(node.hand)),
+        }
+    }
+
+    fn last_move(&self) -> Option<&Move> {
+        match self {
+            Node::ForceNotCheckmate(_) => None,
+            Node::Normal(node) => node.last_move.as_ref(),
         }
     }
 
@@ -33,18 +95,30 @@ impl Node {
         }
     }
 
-    pub(crate) fn best_boards(self, db: &DB) -> Vec<Board> {
+    pub(crate) fn best_boards(self, parent: &Board) -> Vec<Board> {
         match self {
             Node::ForceNotCheckmate(_) => Vec::new(),
-            Node::Normal(node) => node.best_boards(db),
+            Node::Normal(node) => {
+                let board = node.board(parent);
+                node.best_boards(&board)
+            }
         }
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn board(&self, db: &DB) -> Board {
+    pub(crate) fn best_moves(self) -> Vec<Move> {
+        match self {
+            Node::ForceNotCheckmate(_) => Vec::new(),
+            Node::Normal(node) => node.best_moves(),
+        }
+    }
+
+    pub(crate) fn best_tree(self, with_variations: bool) -> Option<(Move, MoveTree)> {
         match self {
-            Node::ForceNotCheckmate(_) => panic!("ForceNotCheckmateNode has no board"),
-            Node::Normal(node) => node.board(db),
+            Node::ForceNotCheckmate(_) => None,
+            Node::Normal(node) => {
+                let mv = node.last_move?;
+                Some((mv, node.best_tree(with_variations)))
+            }
         }
     }
 
@@ -56,10 +130,10 @@ impl Node {
         }
     }
 
-    pub(crate) fn dump_single_best_board(&self, db: &DB) {
+    pub(crate) fn single_best_boards(&self, parent: &Board, boards: &mut Vec<Board>) {
         match self {
             Node::ForceNotCheckmate(_) => {}
-            Node::Normal(node) => node.dump_single_best_board(db),
+            Node::Normal(node) => node.single_best_boards(&node.board(parent), boards),
         }
     }
 }
@@ -73,13 +147,13 @@ impl MultiSetValue for Node {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(super) enum Position {
+pub(crate) enum Position {
     Offense,
     Defense,
 }
 
 impl Position {
-    fn reversed(&self) -> Position {
+    pub(crate) fn reversed(&self) -> Position {
         match self {
             Position::Offense => Position::Defense,
             Position::Defense => Position::Offense,
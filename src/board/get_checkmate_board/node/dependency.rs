@@ -0,0 +1,112 @@
+use crate::db::Key;
+use std::collections::HashSet;
+
+/// What the result of a node rests on besides its position, so that it may not hold on another path to it.
+///
+/// Every move of the attacker is a check, so a position repeated on the path is a perpetual check,
+/// which the attacker loses, and a fourfold repetition is no mate anyway. The search does not go on
+/// from such a position, which is only not mate because of the path that leads to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Dependency {
+    /// The result holds on any path.
+    #[default]
+    None,
+    /// The result holds on a path through all these positions, sorted, as it rests on repeating them.
+    /// More repetitions only help the defender, so a result that is not mate holds there.
+    Repetitions(Vec<Key>),
+    /// The result rests on the depth limit.
+    Depth,
+}
+
+impl Dependency {
+    pub(crate) fn repetition(key: Key) -> Dependency {
+        Dependency::Repetitions(vec![key])
+    }
+
+    /// Rank to choose the result that holds on most paths.
+    fn rank(&self) -> usize {
+        match self {
+            Dependency::None => 0,
+            Dependency::Repetitions(keys) => keys.len(),
+            Dependency::Depth => usize::MAX,
+        }
+    }
+
+    /// The dependency of a result that needs both.
+    pub(crate) fn union(self, other: &Dependency) -> Dependency {
+        match (self, other) {
+            (Dependency::Depth, _) | (_, Dependency::Depth) => Dependency::Depth,
+            (dependency, Dependency::None) => dependency,
+            (Dependency::None, other) => other.clone(),
+            (Dependency::Repetitions(mut keys), Dependency::Repetitions(others)) => {
+                keys.extend(others);
+                keys.sort_unstable();
+                keys.dedup();
+                Dependency::Repetitions(keys)
+            }
+        }
+    }
+
+    /// The dependency that holds on most paths among those of results that will do alike.
+    pub(crate) fn weakest<'a, I>(dependencies: I) -> Dependency
+    where
+        I: IntoIterator<Item = &'a Dependency>,
+    {
+        dependencies
+            .into_iter()
+            .min_by_key(|dependency| dependency.rank())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The dependency of the result of the node of `key`, which is on the path of all its descendants.
+    pub(crate) fn without(self, key: &Key) -> Dependency {
+        match self {
+            Dependency::Repetitions(keys) => {
+                let keys: Vec<_> = keys.into_iter().filter(|k| k != key).collect();
+                if keys.is_empty() {
+                    Dependency::None
+                } else {
+                    Dependency::Repetitions(keys)
+                }
+            }
+            dependency => dependency,
+        }
+    }
+
+    /// Whether a result of a node whose path is `history` holds.
+    pub(crate) fn holds_on(&self, history: &HashSet<Key>) -> bool {
+        match self {
+            Dependency::None => true,
+            Dependency::Repetitions(keys) => keys.iter().all(|key| history.contains(key)),
+            Dependency::Depth => false,
+        }
+    }
+
+    pub(crate) fn is_path_dependent(&self) -> bool {
+        *self != Dependency::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependency() {
+        let key = |n: u64| n.to_le_bytes();
+        let a = Dependency::repetition(key(1));
+        let b = Dependency::repetition(key(2));
+        let both = a.clone().union(&b);
+        assert_eq!(both, Dependency::Repetitions(vec![key(1), key(2)]));
+        assert_eq!(a.clone().union(&Dependency::Depth), Dependency::Depth);
+        assert_eq!(Dependency::weakest([&both, &a, &Dependency::Depth]), a);
+        assert_eq!(both.clone().without(&key(1)), b);
+        assert_eq!(a.clone().without(&key(1)), Dependency::None);
+
+        let history = HashSet::from([key(1), key(3)]);
+        assert!(a.holds_on(&history));
+        assert!(!both.holds_on(&history));
+        assert!(!Dependency::Depth.holds_on(&history));
+    }
+}
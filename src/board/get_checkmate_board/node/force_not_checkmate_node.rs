@@ -1,12 +1,14 @@
-use super::{PnDn, Position};
+use super::{Dependency, PnDn, Position};
 
 pub(crate) struct ForceNotCheckmateNode {
     pub(super) pndn: PnDn,
+    pub(super) dependency: Dependency,
 }
 
 impl ForceNotCheckmateNode {
-    pub(super) fn new(position: Position) -> Self {
+    pub(super) fn new(position: Position, dependency: Dependency) -> Self {
         Self {
+            dependency,
             pndn: match position {
                 Position::Offense => PnDn {
                     pn: u32::MAX,
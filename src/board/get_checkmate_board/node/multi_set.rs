@@ -22,11 +22,9 @@ impl<'a, 'b, T: MultiSetValue> Iterator for MultiSetIterator<'a, 'b, T> {
                 return Some(v);
             }
         }
-        let Some(set) = self.map_values.next() else {
-            return None;
-        };
+        let set = self.map_values.next()?;
         self.set_iter = Some(set.iter());
-        return self.next();
+        self.next()
     }
 }
 
@@ -43,11 +41,9 @@ impl<'a, 'b, T: MultiSetValue> Iterator for MultiSetIteratorMut<'a, 'b, T> {
                 return Some(v);
             }
         }
-        let Some(set) = self.map_values.next() else {
-            return None;
-        };
+        let set = self.map_values.next()?;
         self.set_iter = Some(set.iter_mut());
-        return self.next();
+        self.next()
     }
 }
 
@@ -59,18 +55,18 @@ impl<T: MultiSetValue> MultiSet<T> {
     }
 
     pub(crate) fn iter(&self) -> MultiSetIterator<'_, '_, T> {
-        return MultiSetIterator {
+        MultiSetIterator {
             map_values: self.inner.values(),
             set_iter: None,
-        };
+        }
     }
 
     #[allow(dead_code)]
     pub(crate) fn iter_mut(&mut self) -> MultiSetIteratorMut<'_, '_, T> {
-        return MultiSetIteratorMut {
+        MultiSetIteratorMut {
             map_values: self.inner.values_mut(),
             set_iter: None,
-        };
+        }
     }
 
     pub(crate) fn push_back(&mut self, value: T) {
@@ -88,7 +84,7 @@ impl<T: MultiSetValue> MultiSet<T> {
         if !list.is_empty() {
             self.inner.insert(key, list);
         }
-        return Some(value);
+        Some(value)
     }
 
     pub(crate) fn pop_same_key_fronts(&mut self) -> Option<LinkedList<T>> {
@@ -97,9 +93,10 @@ impl<T: MultiSetValue> MultiSet<T> {
 
     pub(crate) fn peak_front(&self) -> Option<&T> {
         let (_, list) = self.inner.iter().next()?;
-        return list.front();
+        list.front()
     }
 
+    #[cfg(test)]
     pub(crate) fn is_empty(&self) -> bool {
         self.inner.is_empty()
     }
@@ -1,11 +1,10 @@
 mod board_node;
 
-use super::{Board, ForceNotCheckmateNode, MultiSet, Node, PnDn, Position};
-use crate::{
-    db::{Key, DB},
-    Error, NextBoardKind,
+use super::{
+    Board, Dependency, ForceNotCheckmateNode, Hand, MultiSet, Node, PnDn, Position, Search, Table,
 };
-use board_node::BoardNode;
+use crate::{db::Key, Error, Move, MoveTree};
+pub(crate) use board_node::{BoardNode, Child, Proof};
 use core::panic;
 use std::collections::HashSet;
 use Position::*;
@@ -14,43 +13,75 @@ pub(crate) struct NormalNode {
     pub(crate) key: Key,
     pub(crate) pndn: PnDn,
     props: Props,
-    next_board_kind: NextBoardKind,
+    pub(crate) last_move: Option<Move>,
+    /// The hand of the attacker.
+    pub(crate) hand: Hand,
+    /// The proof or disproof hand once resolved without depending on the path.
+    pub(crate) result_hand: Option<Hand>,
 }
 
 struct Props {
     position: Position,
     is_children_expanded: bool,
+    /// What the proof or disproof rests on besides the position.
+    dependency: Dependency,
     children: MultiSet<Node>,
 }
 
 impl Props {
     fn expand_children(
         &mut self,
-        next_nodes: Vec<(BoardNode, NextBoardKind)>,
+        next_nodes: Vec<Child>,
+        hand: Hand,
         next_position: Position,
         history: &HashSet<Key>,
         max_depth: Option<usize>,
+        worker: usize,
     ) {
         if let Some(max_depth) = max_depth {
             if history.len() == max_depth {
                 self.children
                     .push_back(Node::ForceNotCheckmate(ForceNotCheckmateNode::new(
                         next_position,
+                        Dependency::Depth,
                     )));
                 return;
             }
         }
 
-        for (next_node, next_board_kind) in next_nodes {
-            if history.contains(&next_node.key) {
+        let mut next_nodes = next_nodes;
+        if worker > 0 {
+            // Children of equal pn and dn are visited in the order they are pushed.
+            let seed = worker as u64;
+            next_nodes.sort_by_key(|child| {
+                (u64::from_le_bytes(child.key) ^ seed).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            });
+        }
+        for child in next_nodes {
+            if history.contains(&child.key) {
                 self.children
                     .push_back(Node::ForceNotCheckmate(ForceNotCheckmateNode::new(
                         next_position,
+                        Dependency::repetition(child.key),
                     )));
                 continue;
             }
 
-            let node = NormalNode::new_with_node(next_node.key, next_position, next_board_kind);
+            let hand = match next_position {
+                Defense => hand.after(&child.mv),
+                Offense => hand,
+            };
+            let mut node =
+                NormalNode::new_with_node(child.key, next_position, Some(child.mv), hand);
+            node.pndn = child.pndn;
+            node.result_hand = child.hand;
+            if let Some((pndn, dependency)) = child.repeated {
+                // Not mate on another path through the same repetitions.
+                if dependency.holds_on(history) {
+                    node.pndn = pndn;
+                    node.props.dependency = dependency;
+                }
+            }
             self.children.push_back(Node::Normal(node));
         }
 
@@ -59,112 +90,360 @@ impl Props {
 }
 
 impl NormalNode {
-    pub(crate) fn board(&self, db: &DB) -> Board {
-        BoardNode::get(db, &self.key).board
+    /// The board of this node, from the reversed board of the parent node.
+    pub(crate) fn board(&self, parent: &Board) -> Board {
+        parent.moved(&self.last_move.expect("the root has no parent"))
     }
 
     pub(crate) fn children(&mut self) -> &mut MultiSet<Node> {
         &mut self.props.children
     }
 
-    fn reload_pndn(&mut self) {
-        if self.props.children.is_empty()
-            && self.props.position == Position::Defense
-            && self.next_board_kind == NextBoardKind::Uchifu
-        {
-            self.pndn = PnDn::new(0, u32::MAX);
-            return;
+    /// Keeps only the front child of this resolved node, which the refutation line goes through.
+    fn keep_front_child(&mut self) {
+        if let Some(front) = self.props.children.pop_front() {
+            self.props.children = MultiSet::new();
+            self.props.children.push_back(front);
+        }
+    }
+
+    /// Whether the attacker is known to mate from this node.
+    fn is_checkmate(&self) -> bool {
+        match self.props.position {
+            Offense => self.pndn.pn == 0,
+            Defense => self.pndn.dn == 0,
+        }
+    }
+
+    fn is_not_checkmate(&self) -> bool {
+        match self.props.position {
+            Offense => self.pndn.dn == 0,
+            Defense => self.pndn.pn == 0,
         }
+    }
+
+    pub(crate) fn dependency(&self) -> &Dependency {
+        &self.props.dependency
+    }
 
+    fn proof(&self) -> Proof {
+        if self.props.dependency.is_path_dependent() {
+            Proof::Unknown
+        } else if self.pndn.pn == 0 {
+            Proof::Proven
+        } else if self.pndn.dn == 0 {
+            Proof::Disproven
+        } else {
+            Proof::Unknown
+        }
+    }
+
+    fn reload_pndn(&mut self) {
         self.pndn = PnDn::new(u32::MAX, 0);
         for child in self.props.children.iter() {
             self.pndn.update_reversed(child.pndn());
         }
+        // A proven node must exceed any threshold.
+        if self.pndn.pn == 0 {
+            self.pndn.dn = u32::MAX;
+        } else if self.pndn.dn == 0 {
+            self.pndn.pn = u32::MAX;
+        }
+
+        let children = &self.props.children;
+        self.props.dependency = if self.pndn.pn == 0 {
+            // Any winning child will do.
+            Dependency::weakest(
+                children
+                    .iter()
+                    .filter(|child| child.pndn().dn == 0)
+                    .map(|child| child.dependency()),
+            )
+        } else if self.pndn.dn == 0 {
+            children.iter().fold(Dependency::None, |dependency, child| {
+                dependency.union(child.dependency())
+            })
+        } else {
+            Dependency::None
+        }
+        .without(&self.key);
+    }
+
+    /// The proof or disproof hand of this resolved node, from those of its children.
+    fn calc_result_hand(&self, board: &Board) -> Hand {
+        let children = &self.props.children;
+        let before = |child: &Node| {
+            let hand = child.result_hand().unwrap_or(Hand::UNLIMITED);
+            child
+                .last_move()
+                .map_or(hand, |mv| match self.props.position {
+                    Offense => hand.before(mv),
+                    Defense => hand,
+                })
+        };
+        if self.pndn.pn == 0 {
+            // The hand of any winning child will do, the one that asks least of the attacker.
+            let winning = children
+                .iter()
+                .filter(|child| child.pndn().dn == 0 && !child.is_path_dependent());
+            match self.props.position {
+                Offense => winning.map(before).min_by_key(|hand| hand.len()),
+                Defense => winning
+                    .filter_map(|child| {
+                        let mv = child.last_move()?;
+                        Some(before(child).escaping(&self.hand, mv))
+                    })
+                    .max_by_key(|hand| hand.len()),
+            }
+            .unwrap_or(self.hand)
+        } else {
+            // All the children are needed.
+            match self.props.position {
+                Offense => children
+                    .iter()
+                    .fold(Hand::UNLIMITED, |hand, child| hand.min(&before(child)))
+                    .for_offense(&self.hand),
+                Defense => children
+                    .iter()
+                    .fold(Hand::default(), |hand, child| hand.max(&before(child)))
+                    .for_defense(&self.hand, &Hand::defender(board, Defense)),
+            }
+        }
     }
 
-    pub(crate) fn new(db: &DB, board: Board) -> NormalNode {
-        let node = BoardNode::get_or_insert(db, board);
-        NormalNode::new_with_node(node.key, Offense, NextBoardKind::Normal)
+    pub(crate) fn new(table: &dyn Table, board: Board) -> NormalNode {
+        let hand = Hand::attacker(&board, Offense);
+        let node = BoardNode::get_or_insert(table, board);
+        NormalNode::new_with_node(node.key, Offense, None, hand)
     }
 
     pub(crate) fn new_with_node(
         board_node_key: Key,
         position: Position,
-        next_board_kind: NextBoardKind,
+        last_move: Option<Move>,
+        hand: Hand,
     ) -> NormalNode {
         NormalNode {
             key: board_node_key,
             pndn: PnDn::new(1, 1),
-            next_board_kind,
+            last_move,
+            hand,
+            result_hand: None,
             props: Props {
                 position,
                 children: MultiSet::new(),
                 is_children_expanded: false,
+                dependency: Dependency::None,
             },
         }
     }
 
-    pub(crate) fn calc_pndn(&mut self, db: &DB, history: &HashSet<Key>, max_depth: Option<usize>) {
+    fn expand(&mut self, search: &Search, board: &Board, history: &HashSet<Key>) {
+        let next_position = self.props.position.reversed();
+        match BoardNode::get_children(search.table, next_position, board) {
+            Ok(children) => {
+                self.props.expand_children(
+                    children,
+                    self.hand,
+                    next_position,
+                    history,
+                    search.options.max_depth,
+                    search.worker,
+                );
+            }
+            Err(e) => match e {
+                Error::CatchKing(board) => panic!("unexpected catch king\n{board}"),
+            },
+        }
+        self.reload_pndn();
+    }
+
+    /// Searches this subtree depth-first until its pn or dn reaches `threshold`,
+    /// `budget` expansions are done or the search is interrupted.
+    /// Boards are not kept in the tree, so `board` must be the board of this node.
+    /// The result is stored in the table, so that transpositions start from it.
+    pub(crate) fn calc_pndn(
+        &mut self,
+        search: &Search,
+        board: &Board,
+        history: &HashSet<Key>,
+        threshold: PnDn,
+        budget: &mut usize,
+    ) {
         let mut copied_history = history.clone();
         copied_history.insert(self.key);
-        if self.props.is_children_expanded {
-            let Some(mut best) = self.props.children.pop_front() else {
+        if !self.props.is_children_expanded {
+            if *budget == 0 || search.is_interrupted() {
                 return;
+            }
+            self.expand(search, board, &copied_history);
+            *budget -= 1;
+        }
+
+        let reversed = board.reversed();
+        while self.pndn.pn < threshold.pn
+            && self.pndn.dn < threshold.dn
+            && *budget > 0
+            && !search.is_interrupted()
+        {
+            let Some(mut best) = self.props.children.pop_front() else {
+                break;
             };
-            best.calc_pndn(db, &copied_history, max_depth);
+            let second_dn = self
+                .props
+                .children
+                .peak_front()
+                .map_or(u32::MAX, |n| n.pndn().dn);
+            // pn of this node is the least dn of the children and dn is the sum of their pn.
+            let child_threshold = PnDn::new(
+                threshold.dn - (self.pndn.dn - best.pndn().pn),
+                threshold.pn.min(second_dn.saturating_add(1)),
+            );
+            best.calc_pndn(search, &reversed, &copied_history, child_threshold, budget);
             self.props.children.push_back(best);
-        } else {
-            let next_position = self.props.position.reversed();
+            self.reload_pndn();
+        }
 
-            match BoardNode::get_child_nodes(db, next_position, &self.key) {
-                Ok(child_nodes) => {
-                    self.props.expand_children(
-                        child_nodes,
-                        next_position,
-                        &copied_history,
-                        max_depth,
-                    );
-                }
-                Err(e) => match e {
-                    Error::CatchKing(board) => {
-                        println!("{}", board);
-                        panic!("unexpected catch king");
-                    }
-                },
-            }
+        let proof = self.proof();
+        if proof != Proof::Unknown {
+            self.result_hand = Some(self.calc_result_hand(board));
+        }
+        BoardNode::store_pndn(
+            search.table,
+            board,
+            self.props.position,
+            self.pndn,
+            proof,
+            self.result_hand,
+            &self.props.dependency,
+        );
+        // A refuted move is not searched again, but its refutation line is read from the tree.
+        if self.last_move.is_some() && self.is_not_checkmate() {
+            self.keep_front_child();
         }
-        self.reload_pndn();
     }
 
-    pub(crate) fn dump_single_best_board(&self, db: &DB) {
+    /// Expands the proof below this checkmate node down to the leaves, so that the answer can be read
+    /// from the tree. Children resolved by the table were not searched, so they are expanded here,
+    /// and searched again within `budget` expansions if the table dropped their proof.
+    /// Returns whether the proof is complete, which it is not if the search is interrupted,
+    /// `budget` runs out or a proof cannot be found again.
+    pub(crate) fn complete_proof(
+        &mut self,
+        search: &Search,
+        board: &Board,
+        history: &HashSet<Key>,
+        budget: &mut usize,
+    ) -> bool {
+        if search.is_interrupted() {
+            return false;
+        }
+        let mut copied_history = history.clone();
+        copied_history.insert(self.key);
+        if !self.props.is_children_expanded {
+            self.expand(search, board, &copied_history);
+        }
+        if !self.is_checkmate() {
+            let threshold = PnDn::new(u32::MAX, u32::MAX);
+            self.calc_pndn(search, board, history, threshold, budget);
+            if !self.is_checkmate() {
+                return false;
+            }
+        }
+
+        let mut mates = Vec::new();
         match self.props.position {
-            Position::Offense => {
-                println!(
-                    "{}\n=================================",
-                    self.board(db).reversed()
-                );
+            Offense => {
+                // One mating move is enough. Those searched in the tree are kept to choose the shortest.
+                let Some(fronts) = self.props.children.pop_same_key_fronts() else {
+                    return false;
+                };
+                let (searched, others): (Vec<_>, Vec<_>) =
+                    fronts.into_iter().partition(|n| n.is_expanded());
+                mates = searched;
+                if mates.is_empty() {
+                    mates.extend(others.into_iter().next());
+                }
             }
-            Position::Defense => {
-                println!("{}\n=================================", self.board(db));
+            Defense => {
+                while let Some(fronts) = self.props.children.pop_same_key_fronts() {
+                    mates.extend(fronts);
+                }
+            }
+        }
+        let reversed = board.reversed();
+        let mut is_complete = true;
+        for mut child in mates {
+            if is_complete {
+                is_complete = child.complete_proof(search, &reversed, &copied_history, budget);
             }
+            self.props.children.push_back(child);
         }
-        self.props.children.peak_front().map(|node| {
-            node.dump_single_best_board(db);
+        is_complete
+    }
+
+    pub(crate) fn is_expanded(&self) -> bool {
+        self.props.is_children_expanded
+    }
+
+    /// Pushes the boards along the current most promising line, all seen from the attacker.
+    pub(crate) fn single_best_boards(&self, board: &Board, boards: &mut Vec<Board>) {
+        let reversed = board.reversed();
+        boards.push(match self.props.position {
+            Position::Offense => reversed.clone(),
+            Position::Defense => board.clone(),
         });
+        if let Some(node) = self.props.children.peak_front() {
+            node.single_best_boards(&reversed, boards);
+        }
     }
 
-    pub(crate) fn best_boards(mut self, db: &DB) -> Vec<Board> {
+    pub(crate) fn best_boards(mut self, board: &Board) -> Vec<Board> {
         let Some(best_nodes) = self.children().pop_same_key_fronts() else {
-            return vec![self.board(db).clone()];
+            return vec![board.clone()];
         };
-        let mut best_boards_vec: Vec<_> =
-            best_nodes.into_iter().map(|n| n.best_boards(db)).collect();
+        let reversed = board.reversed();
+        let mut best_boards_vec: Vec<_> = best_nodes
+            .into_iter()
+            .map(|n| n.best_boards(&reversed))
+            .collect();
         best_boards_vec.sort_unstable_by_key(|h| h.len());
         let mut best_boards = match self.props.position {
             Offense => best_boards_vec.swap_remove(0),
             Defense => best_boards_vec.pop().unwrap(),
         };
-        best_boards.push(self.board(db).clone());
+        best_boards.push(board.clone());
         best_boards
     }
+
+    pub(crate) fn best_moves(mut self) -> Vec<Move> {
+        let Some(best_nodes) = self.children().pop_same_key_fronts() else {
+            return self.last_move.into_iter().collect();
+        };
+        let mut best_moves_vec: Vec<_> = best_nodes.into_iter().map(|n| n.best_moves()).collect();
+        best_moves_vec.sort_unstable_by_key(|h| h.len());
+        let mut best_moves = match self.props.position {
+            Offense => best_moves_vec.swap_remove(0),
+            Defense => best_moves_vec.pop().unwrap(),
+        };
+        best_moves.extend(self.last_move);
+        best_moves
+    }
+
+    pub(crate) fn best_tree(mut self, with_variations: bool) -> MoveTree {
+        let Some(best_nodes) = self.children().pop_same_key_fronts() else {
+            return MoveTree::default();
+        };
+        let mut children: Vec<_> = best_nodes
+            .into_iter()
+            .filter_map(|n| n.best_tree(with_variations))
+            .collect();
+        children.sort_unstable_by_key(|(_, tree)| tree.len());
+        let children = match self.props.position {
+            Offense => children.into_iter().take(1).collect(),
+            Defense if with_variations => children.into_iter().rev().collect(),
+            Defense => children.pop().into_iter().collect(),
+        };
+        MoveTree { children }
+    }
 }
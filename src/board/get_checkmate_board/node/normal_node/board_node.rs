@@ -1,15 +1,43 @@
-use super::Position;
+use super::{Dependency, Hand, PnDn, Position, Table};
 use crate::{
-    db::{get_entity, put_entity, Entity, Key, DB},
-    Board, NextBoardKind, Result,
+    board::get_checkmate_board::hand,
+    db::{Entity, Key},
+    Board, Move, Result,
 };
 use bincode::{Decode, Encode};
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub(crate) struct BoardNode {
     pub(crate) key: Key,
     pub(crate) board: Board,
-    child_keys: Option<Vec<(Key, NextBoardKind)>>,
+    pub(crate) child_keys: Option<Vec<(Key, Move)>>,
+    /// pn and dn of the last search of the position.
+    pub(crate) pndn: PnDn,
+    pub(crate) proof: Proof,
+    /// The proof or disproof hand of `proof`.
+    pub(crate) hand: Option<Hand>,
+    /// The positions whose repetition `pndn` rests on, if it is resolved only on the paths through them.
+    pub(crate) repetitions: Option<Vec<Key>>,
+}
+
+/// A position after a legal move, with what the table knows of it.
+pub(crate) struct Child {
+    pub(crate) key: Key,
+    pub(crate) mv: Move,
+    pub(crate) pndn: PnDn,
+    pub(crate) hand: Option<Hand>,
+    /// The result of the position on the paths through some repetitions.
+    pub(crate) repeated: Option<(PnDn, Dependency)>,
+}
+
+/// Result of the search of a position that holds whichever path leads to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+pub(crate) enum Proof {
+    Unknown,
+    /// pn is 0, the side to move wins.
+    Proven,
+    /// dn is 0, the side to move loses.
+    Disproven,
 }
 
 impl Entity for BoardNode {
@@ -19,19 +47,41 @@ impl Entity for BoardNode {
 }
 
 impl BoardNode {
-    pub(crate) fn get(db: &DB, key: &Key) -> BoardNode {
-        get_entity::<BoardNode>(db, &key).unwrap()
+    /// Looks up `board` by its key. Another board stored under the key is a hash collision,
+    /// which is resolved by probing the following keys, so the key of the returned node may differ.
+    /// A table that evicts nodes may break a chain of probes, after which the board would get a second key,
+    /// so there a colliding board takes the key over instead.
+    pub(crate) fn get_or_insert(table: &dyn Table, board: Board) -> BoardNode {
+        let key = board.key();
+        if table.can_evict() {
+            let node = BoardNode::new(key, board);
+            return match table.claim(&node) {
+                Some(stored) if stored.board == node.board => stored,
+                _ => node,
+            };
+        }
+        BoardNode::probe(table, board, key)
     }
 
-    pub(crate) fn get_or_insert(db: &DB, board: Board) -> BoardNode {
-        let key = board.key();
-        match get_entity(db, &key) {
-            Some(node) => node,
-            None => {
-                let node = BoardNode::new(key, board);
-                put_entity(db, &node);
-                node
+    /// Looks up `board` from `key` on. A free key is claimed in one step, so that threads probing
+    /// at the same time neither store two boards under a key nor a board under two keys.
+    fn probe(table: &dyn Table, board: Board, mut key: Key) -> BoardNode {
+        loop {
+            let stored = match table.get(&key) {
+                Some(stored) => stored,
+                None => {
+                    let node = BoardNode::new(key, board.clone());
+                    match table.claim(&node) {
+                        None => return node,
+                        // Another thread claimed the key first, maybe for another board.
+                        Some(stored) => stored,
+                    }
+                }
+            };
+            if stored.board == board {
+                return stored;
             }
+            key = u64::from_le_bytes(key).wrapping_add(1).to_le_bytes();
         }
     }
 
@@ -40,45 +90,300 @@ impl BoardNode {
             key,
             board,
             child_keys: None,
+            pndn: PnDn::new(1, 1),
+            proof: Proof::Unknown,
+            hand: None,
+            repetitions: None,
         }
     }
 
-    fn is_valid_board(board: &Board, position: Position) -> bool {
-        match position {
-            Position::Offense => !board.is_checked(),
-            Position::Defense => !board.is_checked() && board.is_checking(),
+    /// pn and dn to start a node of this position at `position` with, and the proof or disproof hand
+    /// if it is resolved. A result that depends on the path to the position, like a repetition,
+    /// is not reused. Without a result of its own, the position takes that of another hand that dominates it.
+    pub(crate) fn initial(&self, table: &dyn Table, position: Position) -> (PnDn, Option<Hand>) {
+        match self.proof {
+            Proof::Proven => return (PnDn::new(0, u32::MAX), self.hand),
+            Proof::Disproven => return (PnDn::new(u32::MAX, 0), self.hand),
+            Proof::Unknown => {}
+        }
+        if let Some((is_checkmate, hand)) = hand::lookup(table, &self.board, position) {
+            let pndn = if is_checkmate == (position == Position::Offense) {
+                PnDn::new(0, u32::MAX)
+            } else {
+                PnDn::new(u32::MAX, 0)
+            };
+            return (pndn, Some(hand));
         }
+        if self.pndn.is_resolved() {
+            (PnDn::new(1, 1), None)
+        } else {
+            (self.pndn, None)
+        }
+    }
+
+    /// The result of this position that holds on the paths through the repetitions it rests on.
+    fn repeated(&self) -> Option<(PnDn, Dependency)> {
+        let repetitions = self.repetitions.as_ref()?;
+        (self.proof == Proof::Unknown && self.pndn.is_resolved())
+            .then(|| (self.pndn, Dependency::Repetitions(repetitions.clone())))
+    }
+
+    /// Records the result of a search of `board` at `position`. A proof or disproof already recorded is kept.
+    /// A proof or disproof is also recorded with its hand, for the positions that differ only in hands.
+    /// A result that rests on repetitions is recorded with them, and one that rests on the depth limit is not reused.
+    pub(crate) fn store_pndn(
+        table: &dyn Table,
+        board: &Board,
+        position: Position,
+        pndn: PnDn,
+        proof: Proof,
+        hand: Option<Hand>,
+        dependency: &Dependency,
+    ) {
+        let mut node = BoardNode::get_or_insert(table, board.clone());
+        if node.proof != Proof::Unknown {
+            return;
+        }
+        node.pndn = pndn;
+        node.proof = proof;
+        node.repetitions = match dependency {
+            Dependency::Repetitions(keys) if pndn.is_resolved() => Some(keys.clone()),
+            _ => None,
+        };
+        if proof != Proof::Unknown {
+            let hand = hand.unwrap_or_else(|| Hand::attacker(board, position));
+            let is_checkmate = (proof == Proof::Proven) == (position == Position::Offense);
+            hand::record(table, board, position, is_checkmate, hand);
+            node.hand = Some(hand);
+        }
+        table.put(&node);
     }
 
-    pub(crate) fn get_child_nodes(
-        db: &DB,
+    /// Returns the positions after the legal moves on `board` with their initial pn and dn.
+    /// The table may have dropped any of them, so they are stored again as needed.
+    pub(crate) fn get_children(
+        table: &dyn Table,
         next_position: Position,
-        key: &Key,
-    ) -> Result<Vec<(BoardNode, NextBoardKind)>> {
-        let mut node = get_entity::<BoardNode>(db, key).unwrap();
-        match &node.child_keys {
-            Some(child_keys) => Ok(child_keys
+        board: &Board,
+    ) -> Result<Vec<Child>> {
+        let mut node = BoardNode::get_or_insert(table, board.clone());
+        if let Some(child_keys) = node.child_keys {
+            let children = child_keys
                 .into_iter()
-                .map(|(key, next_board_kind)| (get_entity(db, key).unwrap(), *next_board_kind))
-                .collect()),
-            None => {
-                let child_boards = node.board.reversed().create_all_next_boards()?;
-                let mut child_nodes = Vec::new();
-                let mut child_keys = Vec::new();
-                for (board, next_board_kind) in child_boards {
-                    if !Self::is_valid_board(&board, next_position) {
-                        continue;
+                .map(|(key, mv)| {
+                    let child = table.get(&key);
+                    let (pndn, hand) = child.as_ref().map_or((PnDn::new(1, 1), None), |child| {
+                        child.initial(table, next_position)
+                    });
+                    Child {
+                        key,
+                        mv,
+                        pndn,
+                        hand,
+                        repeated: child.and_then(|child| child.repeated()),
                     }
-                    let node = Self::get_or_insert(db, board);
-                    child_keys.push((node.key, next_board_kind));
-                    child_nodes.push((node, next_board_kind));
-                }
+                })
+                .collect();
+            return Ok(children);
+        }
 
-                node.child_keys = Some(child_keys);
-                put_entity(db, &node);
+        // The attacker only checks, and the defender only gets out of check.
+        let child_boards = match next_position {
+            Position::Defense => board.reversed().create_checking_boards()?,
+            Position::Offense => board.reversed().create_evading_boards()?,
+        };
+        let mut children = Vec::new();
+        for (board, mv) in child_boards {
+            let node = Self::get_or_insert(table, board);
+            let (pndn, hand) = node.initial(table, next_position);
+            children.push(Child {
+                key: node.key,
+                mv,
+                pndn,
+                hand,
+                repeated: node.repeated(),
+            });
+        }
 
-                Ok(child_nodes)
-            }
+        node.child_keys = Some(children.iter().map(|child| (child.key, child.mv)).collect());
+        table.put(&node);
+
+        Ok(children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::get_checkmate_board::table::MemoryTable, db};
+
+    #[test]
+    fn test_collision() {
+        let db = db::open(
+            "/tmp/df_pn.test.collision.rocksdb",
+            true,
+            &Default::default(),
+        )
+        .unwrap();
+        let board = Board::first();
+        let other = board.reversed().create_all_next_boards().unwrap()[0]
+            .0
+            .clone();
+        Table::put(&db, &BoardNode::new(board.key(), other.clone()));
+
+        let node = BoardNode::get_or_insert(&db, board.clone());
+        assert_ne!(node.key, board.key());
+        assert_eq!(node.board, board);
+        assert_eq!(Table::get(&db, &board.key()).unwrap().board, other);
+        assert_eq!(BoardNode::get_or_insert(&db, board).key, node.key);
+    }
+
+    #[test]
+    fn test_collision_with_eviction() {
+        let table = MemoryTable::new(1);
+        let board = Board::first();
+        let other = board.reversed().create_all_next_boards().unwrap()[0]
+            .0
+            .clone();
+        let mut foreign = BoardNode::new(board.key(), other);
+        foreign.pndn = PnDn::new(0, u32::MAX);
+        foreign.proof = Proof::Proven;
+        table.put(&foreign);
+
+        // The board keeps its own key, without the result of the other board.
+        let node = BoardNode::get_or_insert(&table, board.clone());
+        assert_eq!(node.key, board.key());
+        assert_eq!(node.board, board);
+        assert_eq!(node.proof, Proof::Unknown);
+        table.put(&node);
+
+        // Evicting the board from its only slot does not give it another key.
+        let third = Board::first().create_all_next_boards().unwrap()[0]
+            .0
+            .clone();
+        table.put(&BoardNode::new(third.key(), third));
+        assert_eq!(
+            BoardNode::get_or_insert(&table, board.clone()).key,
+            node.key
+        );
+    }
+
+    #[test]
+    fn test_concurrent_collisions() {
+        let db = db::open(
+            "/tmp/df_pn.test.concurrent_collisions.rocksdb",
+            true,
+            &Default::default(),
+        )
+        .unwrap();
+        let boards: Vec<_> = Board::first()
+            .create_all_next_boards()
+            .unwrap()
+            .into_iter()
+            .map(|(board, _)| board)
+            .collect();
+        // All the boards start probing from the same key, along with the threads looking them up.
+        let key = Board::first().key();
+        let barrier = std::sync::Barrier::new(boards.len() * 2);
+        let nodes: Vec<_> = std::thread::scope(|s| {
+            let handles: Vec<_> = boards
+                .iter()
+                .chain(&boards)
+                .map(|board| {
+                    let (db, barrier) = (&db, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        BoardNode::probe(db, board.clone(), key)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let (first, second) = nodes.split_at(boards.len());
+        let mut keys = std::collections::HashSet::new();
+        for ((board, a), b) in boards.iter().zip(first).zip(second) {
+            assert_eq!(a.key, b.key, "a board under two keys");
+            assert!(keys.insert(a.key), "two boards under a key");
+            assert_eq!(Table::get(&db, &a.key).unwrap().board, *board);
         }
     }
+
+    #[test]
+    fn test_store_pndn() {
+        let db = db::open(
+            "/tmp/df_pn.test.store_pndn.rocksdb",
+            true,
+            &Default::default(),
+        )
+        .unwrap();
+        let board = Board::first();
+        let node = BoardNode::get_or_insert(&db, board.clone());
+        assert_eq!(node.initial(&db, Position::Offense).0, PnDn::new(1, 1));
+
+        BoardNode::store_pndn(
+            &db,
+            &board,
+            Position::Offense,
+            PnDn::new(3, 5),
+            Proof::Unknown,
+            None,
+            &Dependency::None,
+        );
+        let node = BoardNode::get_or_insert(&db, board.clone());
+        assert_eq!(node.initial(&db, Position::Offense).0, PnDn::new(3, 5));
+
+        // Not reused, as it is due to the depth limit.
+        BoardNode::store_pndn(
+            &db,
+            &board,
+            Position::Offense,
+            PnDn::new(0, u32::MAX),
+            Proof::Unknown,
+            None,
+            &Dependency::Depth,
+        );
+        let node = BoardNode::get_or_insert(&db, board.clone());
+        assert_eq!(node.initial(&db, Position::Offense).0, PnDn::new(1, 1));
+        assert_eq!(node.repeated(), None);
+
+        // Reused only on the paths through the repetition.
+        let repetition = Dependency::repetition(Board::first().reversed().key());
+        BoardNode::store_pndn(
+            &db,
+            &board,
+            Position::Offense,
+            PnDn::new(u32::MAX, 0),
+            Proof::Unknown,
+            None,
+            &repetition,
+        );
+        let node = BoardNode::get_or_insert(&db, board.clone());
+        assert_eq!(node.initial(&db, Position::Offense).0, PnDn::new(1, 1));
+        assert_eq!(node.repeated(), Some((PnDn::new(u32::MAX, 0), repetition)));
+
+        BoardNode::store_pndn(
+            &db,
+            &board,
+            Position::Offense,
+            PnDn::new(u32::MAX, 0),
+            Proof::Disproven,
+            None,
+            &Dependency::None,
+        );
+        BoardNode::store_pndn(
+            &db,
+            &board,
+            Position::Offense,
+            PnDn::new(2, 2),
+            Proof::Unknown,
+            None,
+            &Dependency::None,
+        );
+        let node = BoardNode::get_or_insert(&db, board);
+        assert_eq!(
+            node.initial(&db, Position::Offense).0,
+            PnDn::new(u32::MAX, 0)
+        );
+    }
 }
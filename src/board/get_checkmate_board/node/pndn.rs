@@ -1,4 +1,6 @@
-#[derive(Clone, Copy, Debug)]
+use bincode::{Decode, Encode};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
 pub(crate) struct PnDn {
     pub(crate) pn: u32,
     pub(crate) dn: u32,
@@ -9,6 +11,11 @@ impl PnDn {
         PnDn { pn, dn }
     }
 
+    /// Whether either number is 0, that is, the side to move is known to win or lose.
+    pub(crate) fn is_resolved(&self) -> bool {
+        self.pn == 0 || self.dn == 0
+    }
+
     pub(crate) fn update_reversed(&mut self, other: &PnDn) {
         if other.dn < self.pn {
             self.pn = other.dn;
@@ -0,0 +1,76 @@
+use super::{node::NormalNode, Table};
+use crate::Board;
+use std::{sync::Arc, time::Duration};
+
+/// A snapshot of a running search, passed to a `ProgressObserver`.
+pub struct Progress<'a> {
+    /// Number of node expansions so far.
+    pub searches: usize,
+    /// Proof number of the root.
+    pub pn: u32,
+    /// Disproof number of the root.
+    pub dn: u32,
+    pub elapsed: Duration,
+    root: &'a NormalNode,
+    root_board: &'a Board,
+    table: &'a dyn Table,
+}
+
+impl<'a> Progress<'a> {
+    pub(super) fn new(
+        searches: usize,
+        elapsed: Duration,
+        root: &'a NormalNode,
+        root_board: &'a Board,
+        table: &'a dyn Table,
+    ) -> Progress<'a> {
+        Progress {
+            searches,
+            pn: root.pndn.pn,
+            dn: root.pndn.dn,
+            elapsed,
+            root,
+            root_board,
+            table,
+        }
+    }
+
+    /// Estimated number of boards stored in the table.
+    pub fn nodes(&self) -> u64 {
+        self.table.len()
+    }
+
+    /// Boards along the current most promising line, from the root and all seen from the attacker.
+    pub fn best_line(&self) -> Vec<Board> {
+        let mut boards = Vec::new();
+        self.root.single_best_boards(self.root_board, &mut boards);
+        boards
+    }
+}
+
+/// Calls `callback` every `interval` node expansions.
+#[derive(Clone)]
+pub struct ProgressObserver {
+    pub interval: usize,
+    pub callback: Arc<dyn Fn(&Progress) + Send + Sync>,
+}
+
+impl ProgressObserver {
+    pub fn new<F>(interval: usize, callback: F) -> ProgressObserver
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        ProgressObserver {
+            interval,
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+impl std::fmt::Debug for ProgressObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressObserver")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,452 @@
+use super::{
+    hand::Hand,
+    node::{BoardNode, Child, Dependency, NormalNode, PnDn, Position},
+    table::ScratchTable,
+    AlternativeMate, Search, SearchOptions,
+};
+use crate::{db::Key, Board, Error, Move, MoveTree};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+use Position::*;
+
+/// Expansions spent to prove a check that the search left unknown, which may mate shorter.
+const CHECK_BUDGET: usize = 64;
+/// Expansions spent to prove that an interposition is useless, where the defender usually has many drops.
+const INTERPOSITION_BUDGET: usize = 256;
+/// Expansions spent to prove each other move of the attacker on the main line.
+const ALTERNATIVE_BUDGET: usize = 1024;
+/// Memory of the table that keeps the searches of the checks apart from the table of the main search.
+const SCRATCH_TABLE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Length of a mate under the rules of tsume, with the attacker's hand left at its end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Solution {
+    pub(crate) len: u32,
+    pub(crate) rest: Hand,
+}
+
+impl Solution {
+    /// The attacker mates shortest, and then with fewest pieces left.
+    fn attacker_order(&self) -> (u32, u32) {
+        (self.len, self.rest.len())
+    }
+
+    /// The defender plays longest, and then leaves the attacker fewest pieces.
+    fn defender_order(&self) -> (u32, Reverse<u32>) {
+        (self.len, Reverse(self.rest.len()))
+    }
+}
+
+/// The solution of a position with the moves that count from it, the best first:
+/// the attacker's one, or all the defender's but useless interpositions.
+struct Solved {
+    solution: Solution,
+    moves: Vec<(Key, Move)>,
+}
+
+enum Entry {
+    Solved(Solved),
+    /// No mate within this many moves was found.
+    Longer(u32),
+}
+
+/// Reads the answer of a proven position under the rules of tsume from the table: the attacker mates shortest,
+/// the defender plays longest and then leaves the attacker fewest pieces, and a useless interposition,
+/// a dropped piece that is taken at once and never used, does not count as a defense.
+///
+/// The mates the search proved are tried first, which bounds the length of the others,
+/// and the checks the search left unknown are searched a little within that length, as they may mate shorter.
+pub(crate) struct Solver<'a> {
+    search: &'a Search<'a>,
+    memo: HashMap<Key, Entry>,
+    /// The positions of `memo` solved with a repetition cutting the search, whose entries hold
+    /// only on the path they were solved on and are not reused.
+    path_dependent: HashSet<Key>,
+    /// Number of times a repetition cut the search so far, by which the results resting on one are told.
+    repetitions: usize,
+    /// The longest mate each unknown check was searched for in vain.
+    failures: HashMap<Key, u32>,
+    /// Where the unknown checks are searched, with a depth limit that the main search does not have.
+    scratch: ScratchTable<'a>,
+}
+
+impl<'a> Solver<'a> {
+    pub(crate) fn new(search: &'a Search<'a>) -> Solver<'a> {
+        Solver {
+            search,
+            memo: HashMap::new(),
+            path_dependent: HashSet::new(),
+            repetitions: 0,
+            failures: HashMap::new(),
+            scratch: ScratchTable::new(search.table, SCRATCH_TABLE_SIZE),
+        }
+    }
+
+    /// Returns the answer from `root`, the root of a search that found a mate on `board`, with the defender's
+    /// other moves if `with_variations` is set, or `None` if the mate could not be read again from the table.
+    pub(crate) fn solve(
+        &mut self,
+        root: &NormalNode,
+        board: &Board,
+        with_variations: bool,
+    ) -> Option<MoveTree> {
+        let mut history = HashSet::new();
+        self.solve_node(root.key, board, Offense, &mut history, u32::MAX)?;
+        Some(self.tree(root.key, with_variations))
+    }
+
+    /// Returns the other moves of the attacker on the main line of the answer from `root` that mate as well.
+    /// The answer must have been solved on `board`.
+    pub(crate) fn alternatives(
+        &mut self,
+        root: &NormalNode,
+        board: &Board,
+    ) -> Vec<AlternativeMate> {
+        let mut alternatives = Vec::new();
+        let mut history = HashSet::new();
+        let (mut key, mut board) = (root.key, board.clone());
+        let mut ply = 0;
+        while let Some(&(main_key, main_mv)) = self.solved(&key).and_then(|s| s.moves.first()) {
+            history.insert(key);
+            let reversed = board.reversed();
+            if ply % 2 == 0 {
+                let children = BoardNode::get_children(self.search.table, Defense, &board)
+                    .expect("the answer does not take the king");
+                for child in children {
+                    if child.key == main_key || history.contains(&child.key) {
+                        continue;
+                    }
+                    let child_board = reversed.moved(&child.mv);
+                    if !self.is_mate(&child, &child_board, &history, u32::MAX, ALTERNATIVE_BUDGET) {
+                        continue;
+                    }
+                    let solution =
+                        self.solve_node(child.key, &child_board, Defense, &mut history, u32::MAX);
+                    alternatives.push(AlternativeMate {
+                        ply,
+                        mv: child.mv,
+                        len: solution.map(|solution| solution.len as usize + 1),
+                        is_finishing: false,
+                    });
+                }
+            }
+            (key, board) = (main_key, reversed.moved(&main_mv));
+            ply += 1;
+        }
+        for alternative in &mut alternatives {
+            alternative.is_finishing = alternative.ply + 1 == ply && alternative.len == Some(1);
+        }
+        alternatives
+    }
+
+    fn tree(&self, key: Key, with_variations: bool) -> MoveTree {
+        let moves = self.solved(&key).map_or(&[][..], |solved| &solved.moves);
+        let moves = if with_variations {
+            moves
+        } else {
+            &moves[..moves.len().min(1)]
+        };
+        let children = moves
+            .iter()
+            .map(|&(key, mv)| (mv, self.tree(key, with_variations)))
+            .collect();
+        MoveTree { children }
+    }
+
+    fn solved(&self, key: &Key) -> Option<&Solved> {
+        match self.memo.get(key) {
+            Some(Entry::Solved(solved)) => Some(solved),
+            _ => None,
+        }
+    }
+
+    /// Solves the node of `board` at `position` if it mates within `bound` moves.
+    fn solve_node(
+        &mut self,
+        key: Key,
+        board: &Board,
+        position: Position,
+        history: &mut HashSet<Key>,
+        bound: u32,
+    ) -> Option<Solution> {
+        match self.memo.get(&key) {
+            _ if self.path_dependent.contains(&key) => {}
+            Some(Entry::Solved(solved)) => {
+                return (solved.solution.len <= bound).then_some(solved.solution);
+            }
+            Some(Entry::Longer(longer)) if *longer >= bound => return None,
+            _ => {}
+        }
+
+        let children = match BoardNode::get_children(self.search.table, position.reversed(), board)
+        {
+            Ok(children) => children,
+            Err(Error::CatchKing(board)) => panic!("unexpected catch king\n{board}"),
+        };
+        history.insert(key);
+        let repetitions = self.repetitions;
+        let solved = match position {
+            Offense => self.solve_offense(board, children, history, bound),
+            Defense => self.solve_defense(board, children, history, bound),
+        };
+        history.remove(&key);
+        if self.repetitions == repetitions {
+            self.path_dependent.remove(&key);
+        } else {
+            self.path_dependent.insert(key);
+        }
+
+        match solved {
+            Some(solved) => {
+                let solution = solved.solution;
+                self.memo.insert(key, Entry::Solved(solved));
+                Some(solution)
+            }
+            None => {
+                self.memo.insert(key, Entry::Longer(bound));
+                None
+            }
+        }
+    }
+
+    fn solve_offense(
+        &mut self,
+        board: &Board,
+        mut children: Vec<Child>,
+        history: &mut HashSet<Key>,
+        bound: u32,
+    ) -> Option<Solved> {
+        if bound == 0 {
+            return None;
+        }
+        // Known mates first, to bound the others.
+        children.sort_by_key(|child| child.pndn.dn != 0);
+        let reversed = board.reversed();
+        let mut best: Option<(Solution, Key, Move)> = None;
+        for child in children {
+            if history.contains(&child.key) {
+                self.repetitions += 1;
+                continue;
+            }
+            // A mate as long as the best may leave fewer pieces.
+            let limit = best.map_or(bound, |(solution, _, _)| solution.len) - 1;
+            let child_board = reversed.moved(&child.mv);
+            if !self.is_mate(&child, &child_board, history, limit, CHECK_BUDGET) {
+                continue;
+            }
+            let Some(solution) = self.solve_node(child.key, &child_board, Defense, history, limit)
+            else {
+                continue;
+            };
+            let solution = Solution {
+                len: solution.len + 1,
+                ..solution
+            };
+            if best.is_none_or(|(best, _, _)| solution.attacker_order() < best.attacker_order()) {
+                best = Some((solution, child.key, child.mv));
+            }
+        }
+        let (solution, key, mv) = best?;
+        Some(Solved {
+            solution,
+            moves: vec![(key, mv)],
+        })
+    }
+
+    fn solve_defense(
+        &mut self,
+        board: &Board,
+        children: Vec<Child>,
+        history: &mut HashSet<Key>,
+        bound: u32,
+    ) -> Option<Solved> {
+        let reversed = board.reversed();
+        let mut defenses = Vec::new();
+        for child in children {
+            if history.contains(&child.key) {
+                // The defender repeats the position.
+                self.repetitions += 1;
+                return None;
+            }
+            if self.is_useless_interposition(board, &child, history, bound) {
+                continue;
+            }
+            let child_board = reversed.moved(&child.mv);
+            let solution = self.solve_node(
+                child.key,
+                &child_board,
+                Offense,
+                history,
+                bound.checked_sub(1)?,
+            )?;
+            defenses.push((solution, child.key, child.mv));
+        }
+        defenses.sort_by_key(|(solution, _, _)| Reverse(solution.defender_order()));
+        let solution = defenses.first().map_or(
+            Solution {
+                len: 0,
+                rest: Hand::attacker(board, Defense),
+            },
+            |(solution, _, _)| Solution {
+                len: solution.len + 1,
+                ..*solution
+            },
+        );
+        Some(Solved {
+            solution,
+            moves: defenses.into_iter().map(|(_, key, mv)| (key, mv)).collect(),
+        })
+    }
+
+    /// Whether the attacker mates after the check `child`. If the search left it unknown,
+    /// it is searched for a mate within `limit` moves with `budget` expansions.
+    fn is_mate(
+        &mut self,
+        child: &Child,
+        board: &Board,
+        history: &HashSet<Key>,
+        limit: u32,
+        mut budget: usize,
+    ) -> bool {
+        if child.pndn.is_resolved() {
+            return child.pndn.dn == 0;
+        }
+        if self
+            .failures
+            .get(&child.key)
+            .is_some_and(|&failed| failed >= limit)
+        {
+            return false;
+        }
+        // The depth counts the nodes on the path, and the mated node must be expanded.
+        let options = SearchOptions {
+            max_depth: Some(history.len().saturating_add(limit as usize + 2)),
+            ..self.search.options.clone()
+        };
+        let search = Search {
+            table: &self.scratch,
+            options: &options,
+            ..*self.search
+        };
+        let hand = Hand::attacker(board, Defense);
+        let mut node = NormalNode::new_with_node(child.key, Defense, Some(child.mv), hand);
+        node.pndn = child.pndn;
+        let threshold = PnDn::new(u32::MAX, u32::MAX);
+        node.calc_pndn(&search, board, history, threshold, &mut budget);
+        if node.pndn.dn == 0 {
+            return true;
+        }
+        // A check that fails only for repeating the path may mate on another.
+        if !matches!(node.dependency(), Dependency::Repetitions(_)) {
+            self.failures.insert(child.key, limit);
+        }
+        false
+    }
+
+    /// Whether the defender's drop `child` on `board` puts a piece that the attacker takes at once
+    /// and then mates even if the piece is given back to the defender, so that it only makes the mate longer.
+    /// With `exclude_useless_interpositions`, a piece that looks useless is also one that the attacker
+    /// takes and mates with.
+    fn is_useless_interposition(
+        &mut self,
+        board: &Board,
+        child: &Child,
+        history: &HashSet<Key>,
+        bound: u32,
+    ) -> bool {
+        if !child.mv.is_drop() {
+            return false;
+        }
+        let square = child.mv.reversed().to;
+        let reversed = board.reversed();
+        let looks_useless = self.search.options.exclude_useless_interpositions
+            && reversed.is_useless_interposition(&child.mv);
+        let child_board = reversed.moved(&child.mv);
+        let Ok(replies) = BoardNode::get_children(self.search.table, Defense, &child_board) else {
+            return false;
+        };
+        for reply in replies {
+            if reply.mv.to != square {
+                continue;
+            }
+            let reply_board = child_board.reversed().moved(&reply.mv);
+            if looks_useless
+                && self.is_mate(&reply, &reply_board, history, bound, INTERPOSITION_BUDGET)
+            {
+                return true;
+            }
+            // The same move without the drop leaves the piece to the defender.
+            let mv = Move {
+                capture: None,
+                ..reply.mv
+            };
+            let given_back = board.moved(&mv);
+            let node = BoardNode::get_or_insert(self.search.table, given_back.clone());
+            let (pndn, hand) = node.initial(self.search.table, Defense);
+            let given_back_child = Child {
+                key: node.key,
+                mv,
+                pndn,
+                hand,
+                repeated: None,
+            };
+            if self.is_mate(
+                &given_back_child,
+                &given_back,
+                history,
+                bound,
+                INTERPOSITION_BUDGET,
+            ) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Board, CheckmateResult, Coord, Piece, PieceKind::*, PieceStatus::*, SearchOptions,
+    };
+
+    /// The knight opens the file to the rook. Every piece dropped on the file is taken by the rook,
+    /// which mates without it, so the mate counts as one move.
+    fn rook_on_file() -> Board {
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Hisha][0] = Piece::moved(Coord::new(8, 8), false);
+        b[Keima][0] = Piece::moved(Coord::new(8, 5), false);
+        b[Kin][0] = Piece::moved(Coord::new(7, 2), false);
+        b[Gin][0] = Piece::moved(Coord::new(6, 1), false);
+        b.reload_bitboards();
+        b
+    }
+
+    #[test]
+    fn test_useless_interposition() {
+        let mut searches = Vec::new();
+        for exclude_useless_interpositions in [false, true] {
+            let options = SearchOptions {
+                table_size: Some(64 * 1024 * 1024),
+                exclude_useless_interpositions,
+                ..Default::default()
+            };
+            let CheckmateResult::Checkmate(moves, n) =
+                rook_on_file().get_checkmate_moves("", true, &options)
+            else {
+                panic!("not checkmate");
+            };
+            assert_eq!(
+                moves.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+                ["2四桂"]
+            );
+            searches.push(n);
+        }
+        // The option reads the answer differently, but prunes nothing from the search.
+        assert_eq!(searches[0], searches[1]);
+    }
+}
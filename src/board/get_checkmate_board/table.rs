@@ -0,0 +1,290 @@
+use super::{
+    hand::HandEntry,
+    node::{BoardNode, Proof},
+    Checkpoint,
+};
+use crate::db::{self, Key, DB};
+use std::{
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// Storage of the positions visited by a search, shared by transpositions.
+pub(crate) trait Table: Send + Sync {
+    fn get(&self, key: &Key) -> Option<BoardNode>;
+    /// The table may drop `node`, or another node to make room for it.
+    fn put(&self, node: &BoardNode);
+    /// Puts `node` unless a node is stored under its key, and returns that node otherwise,
+    /// in one step against the other threads.
+    fn claim(&self, node: &BoardNode) -> Option<BoardNode>;
+    /// Estimated number of stored positions.
+    fn len(&self) -> u64;
+    /// Whether `put` may drop another node, so that a board is not always found again where it was stored.
+    fn can_evict(&self) -> bool {
+        false
+    }
+
+    fn get_hands(&self, key: &Key) -> Option<HandEntry>;
+    /// The table may drop `entry`, or another entry to make room for it.
+    fn put_hands(&self, entry: &HandEntry);
+
+    /// A table that does not outlive the search keeps no checkpoint.
+    fn get_checkpoint(&self) -> Option<Checkpoint> {
+        None
+    }
+
+    fn put_checkpoint(&self, _checkpoint: &Checkpoint) {}
+}
+
+const CHECKPOINT_KEY: &[u8] = b"checkpoint";
+
+/// Hand entries are stored under their key after this prefix, apart from the positions.
+fn hands_key(key: &Key) -> [u8; 9] {
+    let mut bytes = [b'h'; 9];
+    bytes[1..].copy_from_slice(key);
+    bytes
+}
+
+impl Table for DB {
+    fn get(&self, key: &Key) -> Option<BoardNode> {
+        db::get_entity(self, key)
+    }
+
+    fn put(&self, node: &BoardNode) {
+        db::put_entity(self, node);
+    }
+
+    fn claim(&self, node: &BoardNode) -> Option<BoardNode> {
+        let _lock = db::lock_key(self, &node.key);
+        let stored = db::get_entity(self, &node.key);
+        if stored.is_none() {
+            db::put_entity(self, node);
+        }
+        stored
+    }
+
+    fn len(&self) -> u64 {
+        db::estimate_len(self)
+    }
+
+    fn get_hands(&self, key: &Key) -> Option<HandEntry> {
+        db::get_value(self, &hands_key(key))
+    }
+
+    fn put_hands(&self, entry: &HandEntry) {
+        db::put_value(self, &hands_key(&entry.key), entry);
+    }
+
+    fn get_checkpoint(&self) -> Option<Checkpoint> {
+        db::get_value(self, CHECKPOINT_KEY)
+    }
+
+    fn put_checkpoint(&self, checkpoint: &Checkpoint) {
+        db::put_value(self, CHECKPOINT_KEY, checkpoint);
+    }
+}
+
+/// Rough heap memory of a node, that is its board and children, to size the table.
+const NODE_HEAP_SIZE: usize = 2048;
+
+/// A fixed-size hash table in memory with a slot per key. When two keys compete for a slot,
+/// a node proven or disproven whichever path leads to it is kept, as its result is final,
+/// and else the node with more children, as its subtree took more search to build.
+/// Hand entries have slots of their own, where the newer entry is kept.
+pub(crate) struct MemoryTable {
+    slots: Vec<Mutex<Option<BoardNode>>>,
+    hands: Vec<Mutex<Option<HandEntry>>>,
+    len: AtomicU64,
+}
+
+impl MemoryTable {
+    /// Creates a table using about `bytes` of memory.
+    pub(crate) fn new(bytes: usize) -> MemoryTable {
+        let n = (bytes / (size_of::<Mutex<Option<BoardNode>>>() + NODE_HEAP_SIZE)).max(1);
+        let n = 1 << (usize::BITS - 1 - n.leading_zeros());
+        MemoryTable {
+            slots: (0..n).map(|_| Mutex::new(None)).collect(),
+            hands: (0..(n / 8).max(1)).map(|_| Mutex::new(None)).collect(),
+            len: AtomicU64::new(0),
+        }
+    }
+
+    fn slot(&self, key: &Key) -> &Mutex<Option<BoardNode>> {
+        &self.slots[u64::from_le_bytes(*key) as usize & (self.slots.len() - 1)]
+    }
+
+    fn hands_slot(&self, key: &Key) -> &Mutex<Option<HandEntry>> {
+        &self.hands[u64::from_le_bytes(*key) as usize & (self.hands.len() - 1)]
+    }
+
+    fn put_in(&self, slot: &mut Option<BoardNode>, node: &BoardNode) {
+        match slot.as_ref() {
+            None => {
+                self.len.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(old) if old.key != node.key && priority(old) > priority(node) => return,
+            Some(_) => {}
+        }
+        *slot = Some(node.clone());
+    }
+}
+
+/// A table that reads through to `base` but keeps what is put to itself, so that a search with other
+/// options leaves `base` as it was.
+pub(crate) struct ScratchTable<'a> {
+    base: &'a dyn Table,
+    scratch: MemoryTable,
+}
+
+impl<'a> ScratchTable<'a> {
+    /// Creates a table on `base` using about `bytes` of memory for its own nodes.
+    pub(crate) fn new(base: &'a dyn Table, bytes: usize) -> ScratchTable<'a> {
+        ScratchTable {
+            base,
+            scratch: MemoryTable::new(bytes),
+        }
+    }
+}
+
+impl Table for ScratchTable<'_> {
+    fn get(&self, key: &Key) -> Option<BoardNode> {
+        self.scratch.get(key).or_else(|| self.base.get(key))
+    }
+
+    fn put(&self, node: &BoardNode) {
+        self.scratch.put(node);
+    }
+
+    fn claim(&self, node: &BoardNode) -> Option<BoardNode> {
+        self.base
+            .get(&node.key)
+            .filter(|_| self.scratch.get(&node.key).is_none())
+            .or_else(|| self.scratch.claim(node))
+    }
+
+    fn len(&self) -> u64 {
+        self.base.len() + self.scratch.len()
+    }
+
+    fn can_evict(&self) -> bool {
+        true
+    }
+
+    fn get_hands(&self, key: &Key) -> Option<HandEntry> {
+        self.scratch
+            .get_hands(key)
+            .or_else(|| self.base.get_hands(key))
+    }
+
+    fn put_hands(&self, entry: &HandEntry) {
+        self.scratch.put_hands(entry);
+    }
+}
+
+fn priority(node: &BoardNode) -> (bool, usize) {
+    (
+        node.proof != Proof::Unknown,
+        node.child_keys.as_ref().map_or(0, |keys| keys.len() + 1),
+    )
+}
+
+impl Table for MemoryTable {
+    fn get(&self, key: &Key) -> Option<BoardNode> {
+        let slot = self.slot(key).lock().unwrap();
+        slot.as_ref().filter(|node| node.key == *key).cloned()
+    }
+
+    fn put(&self, node: &BoardNode) {
+        let mut slot = self.slot(&node.key).lock().unwrap();
+        self.put_in(&mut slot, node);
+    }
+
+    fn claim(&self, node: &BoardNode) -> Option<BoardNode> {
+        let mut slot = self.slot(&node.key).lock().unwrap();
+        if let Some(stored) = slot.as_ref().filter(|stored| stored.key == node.key) {
+            return Some(stored.clone());
+        }
+        self.put_in(&mut slot, node);
+        None
+    }
+
+    fn len(&self) -> u64 {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    fn can_evict(&self) -> bool {
+        true
+    }
+
+    fn get_hands(&self, key: &Key) -> Option<HandEntry> {
+        let slot = self.hands_slot(key).lock().unwrap();
+        slot.as_ref().filter(|entry| entry.key == *key).cloned()
+    }
+
+    fn put_hands(&self, entry: &HandEntry) {
+        *self.hands_slot(&entry.key).lock().unwrap() = Some(entry.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board::get_checkmate_board::node::{PnDn, Position},
+        Board,
+    };
+
+    #[test]
+    fn test_replacement() {
+        let table = MemoryTable::new(1);
+        assert_eq!(table.slots.len(), 1);
+
+        let board = Board::first();
+        let leaf = BoardNode::get_or_insert(&table, board.reversed());
+        assert_eq!(table.len(), 1);
+        let children = BoardNode::get_children(&table, Position::Offense, &board.reversed());
+        assert_eq!(children.unwrap().len(), 30);
+
+        // The expanded node is kept over all of its children.
+        let node = table.get(&leaf.key).unwrap();
+        assert_eq!(node.child_keys.map(|keys| keys.len()), Some(30));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_resolved_replacement() {
+        let table = MemoryTable::new(1);
+        let board = Board::first();
+        let other = Board::parsed(include_str!("../../../examples/five.txt")).unwrap();
+        let mut proven = BoardNode::get_or_insert(&table, other);
+        proven.pndn = PnDn::new(0, u32::MAX);
+        proven.proof = Proof::Proven;
+        table.put(&proven);
+
+        // Neither the nodes of another position nor those with children take the slot of the proof.
+        let children = BoardNode::get_children(&table, Position::Offense, &board.reversed());
+        assert_eq!(children.unwrap().len(), 30);
+        let node = table.get(&proven.key).unwrap();
+        assert_eq!(node.proof, Proof::Proven);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_scratch_table() {
+        let base = MemoryTable::new(64 * 1024 * 1024);
+        let board = Board::first();
+        let node = BoardNode::get_or_insert(&base, board.reversed());
+        base.put(&node);
+
+        let scratch = ScratchTable::new(&base, 1024 * 1024);
+        assert!(scratch.get(&node.key).is_some());
+        let children = BoardNode::get_children(&scratch, Position::Offense, &board.reversed());
+        assert_eq!(children.unwrap().len(), 30);
+        assert!(scratch.get(&node.key).unwrap().child_keys.is_some());
+        assert!(base.get(&node.key).unwrap().child_keys.is_none());
+        assert_eq!(base.len(), 1);
+    }
+}
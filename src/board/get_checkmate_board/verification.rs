@@ -0,0 +1,28 @@
+use crate::{Move, MoveTree};
+
+/// The answer of a mate with the other moves of the attacker on its main line that mate as well.
+pub struct Verification {
+    pub tree: MoveTree,
+    pub alternatives: Vec<AlternativeMate>,
+}
+
+/// A move of the attacker that mates instead of the one on the main line (余詰).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlternativeMate {
+    /// Index of the move on the main line that it replaces.
+    pub ply: usize,
+    pub mv: Move,
+    /// Number of moves of its mate, counting itself, if it could be read from the table.
+    pub len: Option<usize>,
+    /// Whether it mates at once instead of the last move, which does not spoil the problem.
+    pub is_finishing: bool,
+}
+
+impl Verification {
+    /// Whether the attacker has no other way to mate than the main line but for the last move.
+    pub fn is_unique(&self) -> bool {
+        self.alternatives
+            .iter()
+            .all(|alternative| alternative.is_finishing)
+    }
+}
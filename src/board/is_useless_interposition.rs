@@ -0,0 +1,101 @@
+use super::{Board, Coord, Move, Piece, PieceKind::*, PieceStatus::*};
+
+impl Board {
+    /// Whether `mv`, a move of the side in check, drops a piece between the king and a distant checker
+    /// on a square that no other piece of the side can take the checker back on, so that it looks taken
+    /// at once for nothing. A pinned piece does not defend the square, while the king does next to it.
+    /// Whether taking the piece mates is left to the caller.
+    pub(crate) fn is_useless_interposition(&self, mv: &Move) -> bool {
+        if !mv.is_drop() {
+            return false;
+        }
+        let Some(king) = self[King].iter().find(|p| p.status == MyBoard) else {
+            return false;
+        };
+        let king = king.coord;
+        let Some(checker) = self
+            .attackers(king, EnemyBoard)
+            .iter()
+            .find(|&checker| is_between(checker, mv.to, king))
+        else {
+            return false;
+        };
+        !self
+            .attackers(mv.to, MyBoard)
+            .iter()
+            .any(|defender| self.takes_back(checker, defender, mv.to))
+    }
+
+    /// Whether the piece on `defender` can take the checker from `checker` back on `square`
+    /// without leaving the king in check.
+    fn takes_back(&self, checker: Coord, defender: Coord, square: Coord) -> bool {
+        let (Some((_, checker_kind, checker_i)), Some((_, defender_kind, defender_i))) =
+            (self.piece_at(&checker), self.piece_at(&defender))
+        else {
+            return false;
+        };
+        let mut board = self.clone();
+        board[checker_kind][checker_i] = Piece::catched(true);
+        board[defender_kind][defender_i].coord = square;
+        board.reload_bitboards();
+        !board.is_checked()
+    }
+}
+
+/// Whether `square` is strictly between `from` and `to` on a rank, a file or a diagonal.
+fn is_between(from: Coord, square: Coord, to: Coord) -> bool {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    if dx != 0 && dy != 0 && dx.abs() != dy.abs() {
+        return false;
+    }
+    let n = dx.abs().max(dy.abs());
+    (1..n).any(|i| Coord::new(from.x + dx.signum() * i, from.y + dy.signum() * i) == square)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Board, Coord, Move, Piece, PieceKind::*, PieceStatus::*};
+
+    #[test]
+    fn test_is_useless_interposition() {
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Hisha][0] = Piece::moved(Coord::new(8, 8), false);
+        b[Kin][0] = Piece::new(7, 4, EnemyBoard, false);
+        b.reload_bitboards();
+        // The defender's king is at (0, 8) and the rook checks it from (0, 0).
+        let b = b.reversed();
+        assert!(b.is_checked());
+        let useless = |y| b.is_useless_interposition(&Move::put(Coord::new(0, y), Kin));
+        assert!(useless(2));
+        assert!(!useless(4), "defended by the gold");
+        assert!(!useless(7), "defended by the king");
+    }
+
+    #[test]
+    fn test_looks_useless_only() {
+        // The defender's king is at (0, 8), the rook checks it from (0, 0),
+        // and the bishop pins the gold at (1, 7), which would defend (0, 6).
+        let mut b = Board::all_catched();
+        b[King][0] = Piece::moved(Coord::new(0, 8), false);
+        b[King][1] = Piece::new(8, 0, EnemyBoard, false);
+        b[Hisha][0] = Piece::new(0, 0, EnemyBoard, false);
+        b[Kaku][0] = Piece::new(4, 4, EnemyBoard, false);
+        b[Kin][0] = Piece::moved(Coord::new(1, 7), false);
+        b.reload_bitboards();
+        assert!(b.is_checked());
+        assert!(b.is_useless_interposition(&Move::put(Coord::new(0, 6), Kin)));
+        assert!(
+            !b.is_useless_interposition(&Move::put(Coord::new(1, 2), Kin)),
+            "off the checking line"
+        );
+
+        b[Kaku][0] = Piece::catched(false);
+        b.reload_bitboards();
+        assert!(
+            !b.is_useless_interposition(&Move::put(Coord::new(0, 6), Kin)),
+            "defended by the gold, no longer pinned"
+        );
+    }
+}
@@ -0,0 +1,540 @@
+use super::{
+    Board, Coord, Locations, Move, MoveTree, Piece, PieceKind, PieceStatus, Pieces, BOARD_SIZE,
+    RANKS,
+};
+use crate::{ParseError, ParseErrorReason::*};
+use std::{cmp::Ordering, fmt::Write};
+use PieceKind::*;
+use PieceStatus::*;
+
+const HAND_ORDER: [PieceKind; 7] = [Hisha, Kaku, Kin, Gin, Keima, Kyousha, Fu];
+const KANJI_NUMBERS: [&str; 10] = ["", "一", "二", "三", "四", "五", "六", "七", "八", "九"];
+
+fn kanji_number(n: usize) -> String {
+    if n < 10 {
+        KANJI_NUMBERS[n].to_string()
+    } else {
+        format!("十{}", KANJI_NUMBERS[n - 10])
+    }
+}
+
+fn zenkaku_digit(n: i8) -> char {
+    char::from_u32('０' as u32 + n as u32).unwrap()
+}
+
+fn destination(mv: &Move, prev: Option<&Move>) -> String {
+    if prev.is_some_and(|prev| prev.to == mv.to) {
+        "同　".to_string()
+    } else {
+        format!(
+            "{}{}",
+            zenkaku_digit(BOARD_SIZE as i8 - mv.to.x),
+            RANKS[mv.to.y as usize]
+        )
+    }
+}
+
+fn promotion(mv: &Move) -> &'static str {
+    if mv.promote {
+        "成"
+    } else if mv.can_promote {
+        "不成"
+    } else {
+        ""
+    }
+}
+
+/// `mv` is absolute and `board` is seen from the side playing it, as everywhere else in the search.
+fn relative(mv: &Move, ply: usize) -> Move {
+    if ply % 2 == 1 {
+        *mv
+    } else {
+        mv.reversed()
+    }
+}
+
+fn kif_move(mv: &Move, prev: Option<&Move>) -> String {
+    let from = match mv.from {
+        Some(from) => format!("({}{})", BOARD_SIZE as i8 - from.x, from.y + 1),
+        None => "打".to_string(),
+    };
+    format!(
+        "{}{}{}{from}",
+        destination(mv, prev),
+        mv.kind.notation(mv.is_changed),
+        promotion(mv)
+    )
+}
+
+/// Golds, silvers and the promoted small pieces, which `直` is written for when they move straight forward.
+fn moves_like_gold(kind: PieceKind, is_changed: bool) -> bool {
+    match kind {
+        Kin | Gin => true,
+        Fu | Kyousha | Keima => is_changed,
+        Kaku | Hisha | King => false,
+    }
+}
+
+/// The piece is told apart from the others of its kind that can reach the square as in KI2,
+/// by its movement (`上`, `引`, `寄`), then its position (`右`, `左`, `直`), then both.
+fn ki2_move(board: &Board, ply: usize, mv: &Move, prev: Option<&Move>) -> String {
+    let mark = if ply % 2 == 1 { '▲' } else { '△' };
+    let rel = relative(mv, ply);
+    let others: Vec<_> = board
+        .create_all_next_boards()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(b, m)| {
+            m.to == rel.to
+                && m.kind == rel.kind
+                && m.is_changed == rel.is_changed
+                && m.from.is_some()
+                && m.from != rel.from
+                && !b.is_checked()
+        })
+        .filter_map(|(_, m)| m.from)
+        .collect();
+
+    let relative_move = match rel.from {
+        None if others.is_empty() => String::new(),
+        None => "打".to_string(),
+        Some(_) if others.is_empty() => String::new(),
+        Some(from) => {
+            let movement = |from: &Coord| match from.y.cmp(&rel.to.y) {
+                Ordering::Greater => "上",
+                Ordering::Less => "引",
+                Ordering::Equal => "寄",
+            };
+            // Right or left of all the `others`, as seen from the side playing the move.
+            let position = |others: &[&Coord]| {
+                if others.iter().all(|o| o.x < from.x) {
+                    Some("右")
+                } else if others.iter().all(|o| o.x > from.x) {
+                    Some("左")
+                } else {
+                    None
+                }
+            };
+            let same_movement: Vec<_> = others
+                .iter()
+                .filter(|o| movement(o) == movement(&from))
+                .collect();
+            let is_straight = from.x == rel.to.x
+                && from.y == rel.to.y + 1
+                && moves_like_gold(rel.kind, rel.is_changed);
+            if same_movement.is_empty() {
+                movement(&from).to_string()
+            } else if is_straight {
+                "直".to_string()
+            } else if let Some(position) = position(&others.iter().collect::<Vec<_>>()) {
+                position.to_string()
+            } else {
+                // Told apart from the pieces on its side by the movement, as in `右上`.
+                format!(
+                    "{}{}",
+                    position(&same_movement).unwrap_or(""),
+                    movement(&from)
+                )
+            }
+        }
+    };
+
+    format!(
+        "{mark}{}{}{relative_move}{}",
+        destination(mv, prev),
+        mv.kind.notation(mv.is_changed),
+        promotion(mv)
+    )
+}
+
+struct Line {
+    start: usize,
+    moves: Vec<String>,
+}
+
+/// Collects the line starting with `first`, followed by the variations branching from it, latest first.
+fn collect_lines<F>(
+    lines: &mut Vec<Line>,
+    first: &(Move, MoveTree),
+    board: Board,
+    start: usize,
+    prev: Option<Move>,
+    notation: &F,
+) where
+    F: Fn(&Board, usize, &Move, Option<&Move>) -> String,
+{
+    let mut moves = Vec::new();
+    let mut branches = Vec::new();
+    let (mut current, mut board, mut ply, mut prev) = (Some(first), board, start, prev);
+    while let Some((mv, next)) = current {
+        moves.push(notation(&board, ply, mv, prev.as_ref()));
+        board = board.moved(&relative(mv, ply)).reversed();
+        if next.children.len() > 1 {
+            branches.push((next, board.clone(), ply + 1, *mv));
+        }
+        current = next.children.first();
+        prev = Some(*mv);
+        ply += 1;
+    }
+    lines.push(Line { start, moves });
+
+    for (node, board, ply, prev) in branches.into_iter().rev() {
+        for child in &node.children[1..] {
+            collect_lines(lines, child, board.clone(), ply, Some(prev), notation);
+        }
+    }
+}
+
+const REST_ALL: &str = "残り全部";
+const ENEMY_HANDS: &str = "後手の持駒：";
+const MY_HANDS: &str = "先手の持駒：";
+
+impl Board {
+    /// Parses a BOD diagram, as found in KIF headers.
+    ///
+    /// `残り全部` in a hand means every piece that is not placed elsewhere.
+    /// If the diagram says `後手番`, the board is reversed so that the side to move is `My`.
+    pub fn from_bod<S>(str: S) -> Result<Board, ParseError>
+    where
+        S: AsRef<str>,
+    {
+        let mut pieces = Pieces::new();
+        let mut locations = Locations::new();
+        let mut rest_is_mine = None;
+        let mut is_white_turn = false;
+        let mut y = 0;
+        let mut line_count = 0;
+
+        for (line, line_number) in str.as_ref().lines().map(|l| l.trim_end()).zip(1..) {
+            line_count = line_number;
+            let hands = if let Some(hands) = line.strip_prefix(ENEMY_HANDS) {
+                Some((hands, false))
+            } else {
+                line.strip_prefix(MY_HANDS).map(|hands| (hands, true))
+            };
+            if let Some((hands, is_mine)) = hands {
+                if hands.trim_end_matches('　') == REST_ALL {
+                    rest_is_mine = Some(is_mine);
+                    continue;
+                }
+                let column = MY_HANDS.chars().count() + 1;
+                for (kind, n) in PieceKind::parse_bod_hands(hands, line_number, column)? {
+                    pieces[kind].extend((0..n).map(|_| Piece::catched(is_mine)));
+                }
+            } else if let Some(row) = line.strip_prefix('|') {
+                if y == BOARD_SIZE as i8 {
+                    return Err(ParseError::new(InvalidField, Some('|')).at(line_number, 1));
+                }
+                let mut chars = row.chars().zip(2..);
+                for x in 0..BOARD_SIZE as i8 {
+                    let (Some((side, column)), Some((c, _))) = (chars.next(), chars.next()) else {
+                        let column = line.chars().count() + 1;
+                        return Err(ParseError::new(UnexpectedEnd, None).at(line_number, column));
+                    };
+                    if c == '・' {
+                        continue;
+                    }
+                    let status = match side {
+                        ' ' | '^' => MyBoard,
+                        'v' => EnemyBoard,
+                        _ => {
+                            return Err(
+                                ParseError::new(UnknownGlyph, Some(side)).at(line_number, column)
+                            )
+                        }
+                    };
+                    let (kind, is_changed) = PieceKind::safe_parse(c).ok_or(
+                        ParseError::new(UnknownGlyph, Some(c)).at(line_number, column + 1),
+                    )?;
+                    pieces[kind].push(Piece::new(x, y, status, is_changed));
+                    locations.insert(Coord::new(x, y), (line_number, column + 1));
+                }
+                y += 1;
+            } else if line.starts_with("後手番") {
+                is_white_turn = true;
+            }
+        }
+        if y != BOARD_SIZE as i8 {
+            return Err(ParseError::new(UnexpectedEnd, None).at(line_count + 1, 1));
+        }
+
+        if let Some(is_mine) = rest_is_mine {
+            pieces.fill_remaining(is_mine);
+        }
+        pieces.validate(&locations)?;
+
+        let mut board = Board::new(pieces);
+        board.reload_bitboards();
+        if is_white_turn {
+            Ok(board.reversed())
+        } else {
+            Ok(board)
+        }
+    }
+
+    fn lines<F>(&self, tree: &MoveTree, notation: F) -> Vec<Line>
+    where
+        F: Fn(&Board, usize, &Move, Option<&Move>) -> String,
+    {
+        let mut lines = Vec::new();
+        for child in &tree.children {
+            collect_lines(&mut lines, child, self.clone(), 1, None, &notation);
+        }
+        if lines.is_empty() {
+            lines.push(Line {
+                start: 1,
+                moves: Vec::new(),
+            });
+        }
+        lines
+    }
+
+    /// Writes the board as a BOD diagram with `My` as 先手.
+    pub(crate) fn dump_bod_to<W>(&self, w: &mut W) -> std::fmt::Result
+    where
+        W: Write,
+    {
+        let hands = |status: PieceStatus| -> String {
+            let hands: String = HAND_ORDER
+                .iter()
+                .filter_map(|&kind| {
+                    let n = self[kind].iter().filter(|p| p.status == status).count();
+                    match n {
+                        0 => None,
+                        1 => Some(format!("{}　", kind.notation(false))),
+                        n => Some(format!("{}{}　", kind.notation(false), kanji_number(n))),
+                    }
+                })
+                .collect();
+            if hands.is_empty() {
+                "なし".to_string()
+            } else {
+                hands
+            }
+        };
+
+        writeln!(w, "{ENEMY_HANDS}{}", hands(EnemyHand))?;
+        writeln!(w, "  ９ ８ ７ ６ ５ ４ ３ ２ １")?;
+        writeln!(w, "+---------------------------+")?;
+        for (y, rank) in RANKS.iter().enumerate() {
+            write!(w, "|")?;
+            for x in 0..BOARD_SIZE {
+                match self.piece_at(&Coord::new(x as i8, y as i8)) {
+                    None => write!(w, " ・")?,
+                    Some((p, kind, _)) => {
+                        let side = if p.status == MyBoard { ' ' } else { 'v' };
+                        write!(w, "{side}{}", kind.notation(p.is_changed))?;
+                    }
+                }
+            }
+            writeln!(w, "|{rank}")?;
+        }
+        writeln!(w, "+---------------------------+")?;
+        writeln!(w, "{MY_HANDS}{}", hands(MyHand))
+    }
+
+    /// Returns the KIF record of the mate in `tree` played from this board.
+    /// Variations in `tree` are written as `変化` sections.
+    pub fn to_kif(&self, tree: &MoveTree) -> String {
+        let mut kif = String::new();
+        self.dump_bod_to(&mut kif).unwrap();
+        kif.push_str("手数----指手---------消費時間--\n");
+        for (i, line) in self
+            .lines(tree, |_, _, mv, prev| kif_move(mv, prev))
+            .into_iter()
+            .enumerate()
+        {
+            if i > 0 {
+                writeln!(kif, "\n変化：{}手", line.start).unwrap();
+            }
+            for (j, mv) in line.moves.iter().enumerate() {
+                writeln!(kif, "{:>4} {mv}", line.start + j).unwrap();
+            }
+            if i == 0 {
+                writeln!(kif, "まで{}手で詰み", line.moves.len()).unwrap();
+            }
+        }
+        kif
+    }
+
+    /// Returns the KI2 record of the mate in `tree` played from this board.
+    pub fn to_ki2(&self, tree: &MoveTree) -> String {
+        let mut ki2 = String::new();
+        self.dump_bod_to(&mut ki2).unwrap();
+        for (i, line) in self.lines(tree, ki2_move).into_iter().enumerate() {
+            if i > 0 {
+                writeln!(ki2, "\n変化：{}手", line.start).unwrap();
+            }
+            for moves in line.moves.chunks(6) {
+                writeln!(ki2, "{}", moves.join(" ")).unwrap();
+            }
+            if i == 0 {
+                writeln!(ki2, "まで{}手で詰み", line.moves.len()).unwrap();
+            }
+        }
+        ki2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Coord;
+
+    fn tree(moves: Vec<(Move, MoveTree)>) -> MoveTree {
+        MoveTree { children: moves }
+    }
+
+    #[test]
+    fn test_from_bod() {
+        let board = Board::parsed(include_str!("../../examples/five.txt")).unwrap();
+        let mut bod = String::new();
+        board.dump_bod_to(&mut bod).unwrap();
+        assert_eq!(Board::from_bod(&bod).unwrap(), board);
+
+        let bod = "# 詰将棋
+後手の持駒：残り全部
+  ９ ８ ７ ６ ５ ４ ３ ２ １
++---------------------------+
+| ・ ・ ・ ・ ・ ・ ・v香 ・|一
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|二
+| ・ ・ ・ ・v金 ・ と ・ ・|三
+| ・ ・ ・ ・ ・ ・ 銀 ・v金|四
+| ・ ・ ・ ・ ・ ・v玉 ・ ・|五
+| ・ ・ ・ ・ ・v歩v歩v歩 ・|六
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|七
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|八
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|九
++---------------------------+
+先手の持駒：角　金　銀　
+手数----指手---------消費時間--
+";
+        assert_eq!(Board::from_bod(bod).unwrap(), board);
+
+        let bod = bod.replace("v香", "v犬");
+        assert_eq!(
+            Board::from_bod(bod),
+            Err(ParseError::new(UnknownGlyph, Some('犬')).at(5, 17))
+        );
+    }
+
+    #[test]
+    fn test_to_kif() {
+        let board = Board::from_sfen("4k4/9/4P4/9/9/9/9/9/9 b GR 1").unwrap();
+        let kin = Move::put(Coord::new(4, 1), Kin);
+        let tree = tree(vec![(kin, MoveTree::default())]);
+        assert_eq!(
+            board.to_kif(&tree),
+            "後手の持駒：飛　角二　金三　銀四　桂四　香四　歩十七　
+  ９ ８ ７ ６ ５ ４ ３ ２ １
++---------------------------+
+| ・ ・ ・ ・v玉 ・ ・ ・ ・|一
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|二
+| ・ ・ ・ ・ 歩 ・ ・ ・ ・|三
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|四
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|五
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|六
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|七
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|八
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|九
++---------------------------+
+先手の持駒：飛　金　
+手数----指手---------消費時間--
+   1 ５二金打
+まで1手で詰み
+"
+        );
+    }
+
+    #[test]
+    fn test_ki2_relative_moves() {
+        let ki2 = |sfen: &str, from: (i8, i8), to: (i8, i8)| {
+            let board = Board::from_sfen(sfen).unwrap();
+            let (_, mv) = board
+                .create_all_next_boards()
+                .unwrap()
+                .into_iter()
+                .find(|(_, mv)| {
+                    mv.from == Some(Coord::new(from.0, from.1))
+                        && mv.to == Coord::new(to.0, to.1)
+                        && !mv.promote
+                })
+                .unwrap();
+            ki2_move(&board, 1, &mv, None)
+        };
+
+        let golds = "8k/9/9/9/9/3GGG3/9/9/K8 b - 1";
+        assert_eq!(ki2(golds, (3, 5), (4, 4)), "▲５五金左");
+        assert_eq!(ki2(golds, (4, 5), (4, 4)), "▲５五金直");
+        assert_eq!(ki2(golds, (5, 5), (4, 4)), "▲５五金右");
+
+        let golds = "8k/9/9/9/5G3/3G1G3/9/9/K8 b - 1";
+        assert_eq!(ki2(golds, (3, 5), (4, 4)), "▲５五金左");
+        assert_eq!(ki2(golds, (5, 5), (4, 4)), "▲５五金右上");
+        assert_eq!(ki2(golds, (5, 4), (4, 4)), "▲５五金寄");
+
+        let silvers = "8k/9/9/9/9/3S1S3/9/3S5/K8 b - 1";
+        assert_eq!(ki2(silvers, (3, 5), (4, 6)), "▲５七銀左引");
+        assert_eq!(ki2(silvers, (5, 5), (4, 6)), "▲５七銀右");
+        assert_eq!(ki2(silvers, (3, 7), (4, 6)), "▲５七銀上");
+
+        let dragons = "8k/9/9/9/9/5+R3/9/4+R4/K8 b - 1";
+        assert_eq!(ki2(dragons, (5, 5), (4, 4)), "▲５五龍右");
+        assert_eq!(ki2(dragons, (4, 7), (4, 4)), "▲５五龍左");
+    }
+
+    #[test]
+    fn test_variations() {
+        let board = Board::from_sfen("4k4/9/4P4/9/9/9/9/9/9 b 2G 1").unwrap();
+        let m = |from: Option<(i8, i8)>, to: (i8, i8), kind| match from {
+            Some((x, y)) => Move::moved(
+                Coord::new(x, y),
+                Coord::new(to.0, to.1),
+                kind,
+                false,
+                false,
+                false,
+                None,
+            ),
+            None => Move::put(Coord::new(to.0, to.1), kind),
+        };
+        let mate = |mv: Move| tree(vec![(mv, MoveTree::default())]);
+        let tree = tree(vec![(
+            m(None, (4, 1), Kin),
+            tree(vec![
+                (m(Some((4, 0)), (3, 0), King), mate(m(None, (3, 1), Kin))),
+                (m(Some((4, 0)), (5, 0), King), mate(m(None, (5, 1), Kin))),
+            ]),
+        )]);
+
+        let kif = board.to_kif(&tree);
+        let moves = &kif[kif.find("手数").unwrap()..];
+        assert_eq!(
+            moves,
+            "手数----指手---------消費時間--
+   1 ５二金打
+   2 ６一玉(51)
+   3 ６二金打
+まで3手で詰み
+
+変化：2手
+   2 ４一玉(51)
+   3 ４二金打
+"
+        );
+
+        let ki2 = board.to_ki2(&tree);
+        let moves = &ki2[ki2.find("|九\n").unwrap() + "|九\n".len()..];
+        assert_eq!(
+            moves,
+            "+---------------------------+
+先手の持駒：金二　
+▲５二金 △６一玉 ▲６二金
+まで3手で詰み
+
+変化：2手
+△４一玉 ▲４二金
+"
+        );
+    }
+}
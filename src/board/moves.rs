@@ -0,0 +1,216 @@
+use super::{Board, Coord, PieceKind, BOARD_SIZE, CONTROL_MAP};
+use bincode::{Decode, Encode};
+
+pub(crate) const RANKS: [char; BOARD_SIZE] = ['一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+/// A move seen from the side that plays it, in the same orientation as the board it was made on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Move {
+    /// `None` for a drop.
+    pub(crate) from: Option<Coord>,
+    pub(crate) to: Coord,
+    pub(crate) kind: PieceKind,
+    /// Whether the piece was already promoted before the move.
+    pub(crate) is_changed: bool,
+    pub(crate) promote: bool,
+    pub(crate) can_promote: bool,
+    pub(crate) capture: Option<PieceKind>,
+}
+
+impl Move {
+    pub(crate) fn moved(
+        from: Coord,
+        to: Coord,
+        kind: PieceKind,
+        is_changed: bool,
+        promote: bool,
+        can_promote: bool,
+        capture: Option<PieceKind>,
+    ) -> Move {
+        Move {
+            from: Some(from),
+            to,
+            kind,
+            is_changed,
+            promote,
+            can_promote,
+            capture,
+        }
+    }
+
+    pub(crate) fn put(to: Coord, kind: PieceKind) -> Move {
+        Move {
+            from: None,
+            to,
+            kind,
+            is_changed: false,
+            promote: false,
+            can_promote: false,
+            capture: None,
+        }
+    }
+
+    /// The same move seen from the other side of the board.
+    pub fn reversed(&self) -> Move {
+        let reverse = |c: Coord| Coord::new(BOARD_SIZE as i8 - c.x - 1, BOARD_SIZE as i8 - c.y - 1);
+        Move {
+            from: self.from.map(reverse),
+            to: reverse(self.to),
+            ..*self
+        }
+    }
+
+    /// Whether this move may check the enemy king at `king`, from where the piece lands
+    /// or by opening a line behind it. Whether the lines are blocked is not looked at.
+    pub(crate) fn may_check(&self, king: Coord) -> bool {
+        let is_changed = self.is_changed || self.promote;
+        let (dx, dy) = (king.x - self.to.x, king.y - self.to.y);
+        let is_direct = CONTROL_MAP[self.kind][is_changed]
+            .iter()
+            .any(|control| control.x == dx && control.y == dy)
+            || match self.kind {
+                PieceKind::Hisha => dx == 0 || dy == 0,
+                PieceKind::Kaku => dx.abs() == dy.abs(),
+                PieceKind::Kyousha => dx == 0 && dy < 0,
+                _ => false,
+            };
+        let is_discovered = self.from.is_some_and(|from| {
+            let (dx, dy) = (king.x - from.x, king.y - from.y);
+            dx == 0 || dy == 0 || dx.abs() == dy.abs()
+        });
+        is_direct || is_discovered
+    }
+
+    pub fn is_drop(&self) -> bool {
+        self.from.is_none()
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        self.promote
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Returns the move in USI notation such as `7g7f`, `8h2b+` or `P*5e`.
+    pub fn to_usi(&self) -> String {
+        let square = |c: Coord| format!("{}{}", BOARD_SIZE as i8 - c.x, (b'a' + c.y as u8) as char);
+        match self.from {
+            Some(from) => format!(
+                "{}{}{}",
+                square(from),
+                square(self.to),
+                if self.promote { "+" } else { "" }
+            ),
+            None => format!("{}*{}", self.kind.sfen_char(), square(self.to)),
+        }
+    }
+
+    /// Returns the move in Japanese notation such as `2三銀成`, or `同銀成` if it lands on `prev`'s destination.
+    pub fn to_japanese(&self, prev: Option<&Move>) -> String {
+        let to = if prev.is_some_and(|prev| prev.to == self.to) {
+            "同".to_string()
+        } else {
+            format!(
+                "{}{}",
+                BOARD_SIZE as i8 - self.to.x,
+                RANKS[self.to.y as usize]
+            )
+        };
+        let suffix = if self.is_drop() {
+            "打"
+        } else if self.promote {
+            "成"
+        } else if self.can_promote {
+            "不成"
+        } else {
+            ""
+        };
+        format!("{to}{}{suffix}", self.kind.notation(self.is_changed))
+    }
+}
+
+/// Moves following a position. The first child is the main line and the rest are variations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveTree {
+    pub(crate) children: Vec<(Move, MoveTree)>,
+}
+
+impl MoveTree {
+    pub fn main_line(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut node = self;
+        while let Some((mv, next)) = node.children.first() {
+            moves.push(*mv);
+            node = next;
+        }
+        moves
+    }
+
+    pub fn variations(&self) -> &[(Move, MoveTree)] {
+        &self.children
+    }
+
+    /// Length of the main line.
+    pub fn len(&self) -> usize {
+        self.children.first().map_or(0, |(_, next)| next.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+impl Board {
+    /// Returns the board after `mv`, still seen from the side that played it.
+    pub(crate) fn moved(&self, mv: &Move) -> Board {
+        let mut board = self.clone();
+        board.do_move(mv);
+        board
+    }
+}
+
+impl std::fmt::Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_japanese(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PieceKind::*;
+
+    #[test]
+    fn test_notation() {
+        let m = Move::moved(
+            Coord::new(6, 3),
+            Coord::new(7, 2),
+            Gin,
+            false,
+            true,
+            true,
+            None,
+        );
+        assert_eq!(m.to_usi(), "3d2c+");
+        assert_eq!(m.to_string(), "2三銀成");
+
+        let m = Move::moved(
+            Coord::new(8, 1),
+            Coord::new(7, 2),
+            King,
+            false,
+            false,
+            false,
+            Some(Gin),
+        );
+        assert_eq!(m.to_usi(), "1b2c");
+        assert_eq!(m.to_japanese(Some(&m)), "同玉");
+
+        let m = Move::put(Coord::new(4, 4), Kaku);
+        assert_eq!(m.to_usi(), "B*5e");
+        assert_eq!(m.to_string(), "5五角打");
+        assert_eq!(m.reversed().to_usi(), "B*5e");
+    }
+}
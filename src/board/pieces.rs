@@ -1,23 +1,26 @@
 mod kind;
 mod piece;
 
-use crate::shared::Set;
+use crate::{shared::Set, ParseError, ParseErrorReason, ParseErrorReason::*, BOARD_SIZE};
 use bincode::{Decode, Encode};
 pub(crate) use kind::Kind;
 pub(crate) use piece::{Coord, Piece, Status};
-use std::ops::{Index, IndexMut};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::{Index, IndexMut},
+};
 use Kind::*;
-
-#[cfg(test)]
 use Status::*;
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash, Encode, Decode)]
+/// Where each piece on the board was read from, as 1-based line and column.
+pub(crate) type Locations = HashMap<Coord, (usize, usize)>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
 pub(crate) struct Pieces {
     elements: [Set<Piece>; 8],
 }
 
 impl Pieces {
-    #[cfg(test)]
     pub(crate) fn first() -> Self {
         Pieces {
             elements: [
@@ -89,25 +92,62 @@ impl Pieces {
         }
     }
 
-    fn assert_len(&self, kind: Kind, expected: usize) {
-        let len = self[kind].len();
-        assert_eq!(len, expected, "{kind}.len() == {len} expected {expected}",);
-    }
+    /// Checks that the pieces form a complete set in a legal position.
+    /// Errors concerning a piece on the board are located with `locations`.
+    pub(crate) fn validate(&self, locations: &Locations) -> Result<(), ParseError> {
+        for kind in Kind::iter().filter(|&k| k != King) {
+            let len = self[kind].len();
+            let expected = kind.total_len();
+            if len != expected {
+                let c = kind.title(false).chars().next();
+                return Err(ParseError::new(WrongPieceTotal { len, expected }, c));
+            }
+        }
+        let len = self[King].len();
+        if !(1..=2).contains(&len) {
+            let c = King.title(false).chars().next();
+            return Err(ParseError::new(WrongKingCount { len }, c));
+        }
 
-    pub(crate) fn validate_len(&self) {
-        self.assert_len(Fu, 18);
-        self.assert_len(Kyousha, 4);
-        self.assert_len(Keima, 4);
-        self.assert_len(Gin, 4);
-        self.assert_len(Kin, 4);
-        self.assert_len(Kaku, 2);
-        self.assert_len(Hisha, 2);
+        let error_at = |kind: Kind, p: &Piece, reason: ParseErrorReason| {
+            let e = ParseError::new(reason, kind.title(p.is_changed).chars().next());
+            match locations.get(&p.coord) {
+                Some(&(line, column)) => e.at(line, column),
+                None => e,
+            }
+        };
+        let mut pawn_files = HashSet::new();
+        for (kind, _, p) in self.iter() {
+            if p.is_changed {
+                continue;
+            }
+            let rank = match p.status {
+                MyBoard => p.coord.y,
+                EnemyBoard => BOARD_SIZE as i8 - p.coord.y - 1,
+                MyHand | EnemyHand => continue,
+            };
+            let no_moves = match kind {
+                Fu if rank == 0 => Some(PawnOnLastRank),
+                Kyousha if rank == 0 => Some(DeadPiece),
+                Keima if rank <= 1 => Some(DeadPiece),
+                _ => None,
+            };
+            if let Some(reason) = no_moves {
+                return Err(error_at(kind, p, reason));
+            }
+            if kind == Fu && !pawn_files.insert((p.status, p.coord.x)) {
+                return Err(error_at(kind, p, DoublePawn));
+            }
+        }
+        Ok(())
+    }
 
-        let king_len = self[King].len();
-        assert!(
-            king_len == 1 || king_len == 2,
-            "{King}.len() == {king_len} expected 1 or 2",
-        );
+    /// Puts every piece missing from the complete set, except kings, into a hand.
+    pub(crate) fn fill_remaining(&mut self, is_mine: bool) {
+        for kind in Kind::iter().filter(|&k| k != King) {
+            let n = kind.total_len().saturating_sub(self[kind].len());
+            self[kind].extend((0..n).map(|_| Piece::catched(is_mine)));
+        }
     }
 
     pub(crate) fn map<F>(&self, f: F) -> Pieces
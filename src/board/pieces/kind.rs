@@ -1,3 +1,4 @@
+use crate::{ParseError, ParseErrorReason::*};
 use bincode::{Decode, Encode};
 use std::collections::HashMap;
 use Kind::*;
@@ -26,48 +27,136 @@ impl Kind {
             '金' => Some((Kin, false)),
             '角' => Some((Kaku, false)),
             '飛' => Some((Hisha, false)),
-            '王' => Some((King, false)),
+            '王' | '玉' => Some((King, false)),
             'と' => Some((Fu, true)),
             '杏' => Some((Kyousha, true)),
             '圭' => Some((Keima, true)),
             '全' => Some((Gin, true)),
             '馬' => Some((Kaku, true)),
-            '龍' => Some((Hisha, true)),
+            '龍' | '竜' => Some((Hisha, true)),
             _ => None,
         }
     }
 
-    pub(crate) fn parse(c: char) -> (Kind, bool) {
-        Kind::safe_parse(c).expect(format!("invalid char: {c}").as_str())
+    pub(crate) fn from_sfen_char(c: char) -> Option<Kind> {
+        match c.to_ascii_uppercase() {
+            'P' => Some(Fu),
+            'L' => Some(Kyousha),
+            'N' => Some(Keima),
+            'S' => Some(Gin),
+            'G' => Some(Kin),
+            'B' => Some(Kaku),
+            'R' => Some(Hisha),
+            'K' => Some(King),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn sfen_char(&self) -> char {
+        match self {
+            Fu => 'P',
+            Kyousha => 'L',
+            Keima => 'N',
+            Gin => 'S',
+            Kin => 'G',
+            Kaku => 'B',
+            Hisha => 'R',
+            King => 'K',
+        }
+    }
+
+    /// Number of pieces of this kind in a complete set.
+    pub(crate) fn total_len(&self) -> usize {
+        match self {
+            Fu => 18,
+            Kyousha | Keima | Gin | Kin => 4,
+            Kaku | Hisha | King => 2,
+        }
+    }
+
+    /// Parses a hand piece at `column` of `line`, which must not be promoted.
+    fn parse_hand(c: char, line: usize, column: usize) -> Result<Kind, ParseError> {
+        match Kind::safe_parse(c) {
+            Some((kind, false)) => Ok(kind),
+            Some((_, true)) => Err(ParseError::new(InvalidHandCount, Some(c)).at(line, column)),
+            None => Err(ParseError::new(UnknownGlyph, Some(c)).at(line, column)),
+        }
     }
 
-    pub(crate) fn parse_hands<S>(s: S) -> HashMap<Kind, u8>
+    /// Parses hands such as `歩x16 香x3 桂` on `line`.
+    pub(crate) fn parse_hands<S>(s: S, line: usize) -> Result<HashMap<Kind, u8>, ParseError>
     where
         S: AsRef<str>,
     {
         let mut map = HashMap::new();
+        let mut column = 1;
         for hand_str in s.as_ref().split(' ') {
+            let start = column;
+            column += hand_str.chars().count() + 1;
             let mut chars = hand_str.chars();
             let Some(c) = chars.next() else { continue };
-            let (kind, is_changed) = Kind::parse(c);
-            if is_changed {
-                panic!("invalid hand: {}", c);
-            }
+            let kind = Kind::parse_hand(c, line, start)?;
 
             let n = {
                 if let Some(c) = chars.next() {
                     if c != 'x' {
-                        panic!("needs `x` before number for {kind} got {c}");
+                        return Err(ParseError::new(InvalidHandCount, Some(c)).at(line, start + 1));
                     }
                     let n = chars.as_str();
-                    n.parse().expect(format!("invalid number {n}").as_str())
+                    n.parse().map_err(|_| {
+                        ParseError::new(InvalidHandCount, n.chars().next()).at(line, start + 2)
+                    })?
                 } else {
                     1
                 }
             };
-            *map.entry(kind).or_insert(0) += n;
+            let total = map.entry(kind).or_insert(0u8);
+            *total = total
+                .checked_add(n)
+                .ok_or(ParseError::new(InvalidHandCount, Some(c)).at(line, start))?;
         }
-        map
+        Ok(map)
+    }
+
+    /// Parses hands written as in BOD diagrams, e.g. `飛　角二　歩十八`, starting at `column` of `line`.
+    pub(crate) fn parse_bod_hands<S>(
+        s: S,
+        line: usize,
+        column: usize,
+    ) -> Result<HashMap<Kind, u8>, ParseError>
+    where
+        S: AsRef<str>,
+    {
+        let mut map = HashMap::new();
+        let mut column = column;
+        for hand_str in s.as_ref().split(['　', ' ']) {
+            let start = column;
+            column += hand_str.chars().count() + 1;
+            let mut chars = hand_str.chars();
+            let Some(c) = chars.next() else { continue };
+            if hand_str == "なし" {
+                continue;
+            }
+            let kind = Kind::parse_hand(c, line, start)?;
+
+            let mut n: u8 = 0;
+            for (c, column) in chars.zip(start + 1..) {
+                let invalid = ParseError::new(InvalidHandCount, Some(c)).at(line, column);
+                let d = match c {
+                    '十' => 10,
+                    _ => match "一二三四五六七八九".chars().position(|k| k == c) {
+                        Some(d) => d as u8 + 1,
+                        None => return Err(invalid),
+                    },
+                };
+                n = n.checked_add(d).ok_or(invalid)?;
+            }
+            let total = map.entry(kind).or_insert(0u8);
+            *total = total
+                .checked_add(n.max(1))
+                .ok_or(ParseError::new(InvalidHandCount, Some(c)).at(line, start))?;
+        }
+        Ok(map)
     }
 
     pub(crate) fn iter() -> impl Iterator<Item = Kind> {
@@ -99,6 +188,14 @@ impl Kind {
             }
         }
     }
+
+    /// The name in move records and BOD diagrams, where a king is written `玉` as usual in tsume.
+    pub(crate) fn notation(&self, is_changed: bool) -> &'static str {
+        match self {
+            King => "玉",
+            _ => self.title(is_changed),
+        }
+    }
 }
 
 impl std::fmt::Display for Kind {
@@ -0,0 +1,352 @@
+use super::PieceStatus::*;
+use super::{Board, Coord, Locations, Piece, PieceKind, Pieces, BOARD_SIZE};
+use crate::{ParseError, ParseErrorReason::*};
+use PieceKind::*;
+
+const HAND_ORDER: [PieceKind; 7] = [Hisha, Kaku, Kin, Gin, Keima, Kyousha, Fu];
+
+impl Board {
+    /// Parses a SFEN string such as `lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1`.
+    ///
+    /// The side to move becomes `My`, so a `w` position is returned reversed.
+    /// Pieces that are neither on the board nor in a hand go to the hand of the side not to move.
+    /// The side to move and the move number are kept for `to_sfen`.
+    pub fn from_sfen<S>(sfen: S) -> Result<Board, ParseError>
+    where
+        S: AsRef<str>,
+    {
+        let mut fields = Vec::new();
+        let mut column = 1;
+        for field in sfen.as_ref().split(' ') {
+            if !field.is_empty() {
+                fields.push((field, column));
+            }
+            column += field.chars().count() + 1;
+        }
+        let mut fields = fields.into_iter();
+        let error = |reason, c: Option<char>, column| ParseError::new(reason, c).at(1, column);
+
+        let (placement, column) = fields.next().ok_or(error(UnexpectedEnd, None, 1))?;
+        let is_black_turn = match fields.next().unwrap_or(("b", column)) {
+            ("b", _) => true,
+            ("w", _) => false,
+            (turn, column) => return Err(error(InvalidField, turn.chars().next(), column)),
+        };
+        let (hands, hands_column) = fields.next().unwrap_or(("-", column));
+        let move_number = match fields.next() {
+            Some((n, column)) => n
+                .parse()
+                .map_err(|_| error(InvalidField, n.chars().next(), column))?,
+            None => 1,
+        };
+
+        let mut pieces = Pieces::new();
+        let mut locations = Locations::new();
+
+        let mut x = 0;
+        let mut y = 0;
+        let mut is_changed = false;
+        for (c, column) in placement.chars().zip(column..) {
+            if c == '/' {
+                if x != BOARD_SIZE {
+                    return Err(error(UnexpectedEnd, Some(c), column));
+                }
+                x = 0;
+                y += 1;
+                if y == BOARD_SIZE {
+                    return Err(error(InvalidField, Some(c), column));
+                }
+                continue;
+            }
+            if let Some(n) = c.to_digit(10) {
+                x += n as usize;
+                if x > BOARD_SIZE {
+                    return Err(error(InvalidField, Some(c), column));
+                }
+                continue;
+            }
+            if c == '+' {
+                is_changed = true;
+                continue;
+            }
+            let kind = PieceKind::from_sfen_char(c)
+                .filter(|&kind| !is_changed || !matches!(kind, Kin | King))
+                .ok_or(error(UnknownGlyph, Some(c), column))?;
+            if x == BOARD_SIZE {
+                return Err(error(InvalidField, Some(c), column));
+            }
+            let status = if c.is_ascii_uppercase() {
+                MyBoard
+            } else {
+                EnemyBoard
+            };
+            pieces[kind].push(Piece::new(x as i8, y as i8, status, is_changed));
+            locations.insert(Coord::new(x as i8, y as i8), (1, column));
+            is_changed = false;
+            x += 1;
+        }
+        if x != BOARD_SIZE || y != BOARD_SIZE - 1 {
+            let end = column + placement.chars().count();
+            return Err(error(UnexpectedEnd, None, end));
+        }
+
+        if hands != "-" {
+            let mut n: usize = 0;
+            for (c, column) in hands.chars().zip(hands_column..) {
+                if let Some(d) = c.to_digit(10) {
+                    // No kind has more pieces than pawns, which also keeps the count from overflowing.
+                    n = n
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add(d as usize))
+                        .filter(|&n| n <= Fu.total_len())
+                        .ok_or(error(InvalidHandCount, Some(c), column))?;
+                    continue;
+                }
+                let kind = PieceKind::from_sfen_char(c)
+                    .filter(|&kind| kind != King)
+                    .ok_or(error(InvalidHandCount, Some(c), column))?;
+                let piece = Piece::catched(c.is_ascii_uppercase());
+                pieces[kind].extend((0..n.max(1)).map(|_| piece));
+                n = 0;
+            }
+        }
+
+        pieces.fill_remaining(!is_black_turn);
+        pieces.validate(&locations)?;
+
+        let mut board = Board::new(pieces);
+        board.reload_bitboards();
+        board.move_number = move_number;
+        if is_black_turn {
+            Ok(board)
+        } else {
+            Ok(board.reversed())
+        }
+    }
+
+    /// Parses the arguments of a USI `position` command, such as `startpos moves 7g7f 3c3d`
+    /// or `sfen <sfen> moves ...`, and plays the moves.
+    ///
+    /// As with `from_sfen`, the side to move after the moves becomes `My`.
+    /// Also returns whether it is black, to map moves back to USI coordinates.
+    pub fn from_usi_position<S>(position: S) -> Result<(Board, bool), ParseError>
+    where
+        S: AsRef<str>,
+    {
+        let mut tokens = Vec::new();
+        let mut column = 1;
+        for token in position.as_ref().split(' ') {
+            if !token.is_empty() {
+                tokens.push((token, column));
+            }
+            column += token.chars().count() + 1;
+        }
+        let moves_index = tokens
+            .iter()
+            .position(|&(token, _)| token == "moves")
+            .unwrap_or(tokens.len());
+        let (position, moves) = tokens.split_at(moves_index);
+
+        let (mut board, mut is_black_turn) = match position {
+            [("startpos", _)] => (Board::first(), true),
+            [("sfen", _), sfen @ ..] if !sfen.is_empty() => {
+                let offset = sfen[0].1 - 1;
+                let sfen_str = sfen.iter().map(|&(t, _)| t).collect::<Vec<_>>().join(" ");
+                let board = Board::from_sfen(sfen_str).map_err(|e| ParseError {
+                    column: e.column.map(|c| c + offset),
+                    ..e
+                })?;
+                (board, sfen.get(1).is_none_or(|&(turn, _)| turn != "w"))
+            }
+            [] => return Err(ParseError::new(UnexpectedEnd, None).at(1, column)),
+            [(token, column), ..] => {
+                return Err(ParseError::new(InvalidField, token.chars().next()).at(1, *column))
+            }
+        };
+
+        for &(usi, column) in moves.iter().skip(1) {
+            let illegal = ParseError::new(IllegalMove, usi.chars().next()).at(1, column);
+            let (next, _) = board
+                .create_all_next_boards()
+                .map_err(|_| illegal.clone())?
+                .into_iter()
+                .find(|(next, mv)| {
+                    let mv = if is_black_turn { *mv } else { mv.reversed() };
+                    mv.to_usi() == usi && !next.is_checked()
+                })
+                .ok_or(illegal)?;
+            let move_number = board.move_number + 1;
+            board = next.reversed();
+            board.move_number = move_number;
+            is_black_turn = !is_black_turn;
+        }
+        Ok((board, is_black_turn))
+    }
+
+    /// Returns the SFEN string of the board with `My` to move, in the side and at the move number
+    /// it was read with, or as black at move 1.
+    pub fn to_sfen(&self) -> String {
+        let board = if self.is_black {
+            self.clone()
+        } else {
+            self.reversed()
+        };
+        let mut sfen = String::new();
+        for y in 0..BOARD_SIZE {
+            if y > 0 {
+                sfen.push('/');
+            }
+            let mut empty = 0;
+            for x in 0..BOARD_SIZE {
+                let Some((p, kind, _)) = board.piece_at(&Coord::new(x as i8, y as i8)) else {
+                    empty += 1;
+                    continue;
+                };
+                if empty > 0 {
+                    sfen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                if p.is_changed {
+                    sfen.push('+');
+                }
+                let c = kind.sfen_char();
+                sfen.push(if p.status == MyBoard {
+                    c
+                } else {
+                    c.to_ascii_lowercase()
+                });
+            }
+            if empty > 0 {
+                sfen.push_str(&empty.to_string());
+            }
+        }
+
+        let mut hands = String::new();
+        for (status, is_mine) in [(MyHand, true), (EnemyHand, false)] {
+            for kind in HAND_ORDER {
+                let n = board[kind].iter().filter(|p| p.status == status).count();
+                if n == 0 {
+                    continue;
+                }
+                if n > 1 {
+                    hands.push_str(&n.to_string());
+                }
+                let c = kind.sfen_char();
+                hands.push(if is_mine { c } else { c.to_ascii_lowercase() });
+            }
+        }
+        if hands.is_empty() {
+            hands.push('-');
+        }
+
+        let turn = if self.is_black { 'b' } else { 'w' };
+        format!("{sfen} {turn} {hands} {}", self.move_number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first() {
+        let sfen = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+        let board = Board::from_sfen(sfen).unwrap();
+        assert_eq!(board, Board::first());
+        assert_eq!(board.to_sfen(), sfen);
+    }
+
+    #[test]
+    fn test_remaining_pieces() {
+        let board = Board::from_sfen("7l1/9/4g1+P2/6S1g/6k2/5ppp1/9/9/9 b BGS 1").unwrap();
+        assert_eq!(
+            board,
+            Board::parsed(include_str!("../../examples/five.txt")).unwrap()
+        );
+        assert_eq!(
+            board.to_sfen(),
+            "7l1/9/4g1+P2/6S1g/6k2/5ppp1/9/9/9 b BGS2rbg2s4n3l14p 1"
+        );
+    }
+
+    #[test]
+    fn test_white_to_move() {
+        let sfen = "9/9/9/9/9/9/9/9/4K4 w G2r2b3g4s4n4l18p 12";
+        let board = Board::from_sfen(sfen).unwrap();
+        assert_eq!(
+            board,
+            Board::from_sfen("4k4/9/9/9/9/9/9/9/9 b 2R2B3G4S4N4L18Pg 1").unwrap()
+        );
+        assert_eq!(board.to_sfen(), sfen);
+        assert_eq!(
+            board.reversed().to_sfen(),
+            "9/9/9/9/9/9/9/9/4K4 b G2r2b3g4s4n4l18p 12"
+        );
+    }
+
+    #[test]
+    fn test_usi_position() {
+        let (board, is_black_turn) =
+            Board::from_usi_position("startpos moves 7g7f 3c3d 8h2b+").unwrap();
+        assert!(!is_black_turn);
+        assert_eq!(
+            board.to_sfen(),
+            "lnsgkgsnl/1r5+B1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL w B 4"
+        );
+
+        let (board, is_black_turn) =
+            Board::from_usi_position("sfen 4k4/9/4P4/9/9/9/9/9/9 b G 1 moves G*5b").unwrap();
+        assert!(!is_black_turn);
+        assert_eq!(
+            board.to_sfen(),
+            "4k4/4G4/4P4/9/9/9/9/9/9 w 2r2b3g4s4n4l17p 2"
+        );
+
+        assert_eq!(
+            Board::from_usi_position("startpos moves 7g7f 7g7f"),
+            Err(ParseError::new(IllegalMove, Some('7')).at(1, 21))
+        );
+        assert_eq!(
+            Board::from_usi_position("sfen 4k4/9/4Q4/9/9/9/9/9/9 b G 1"),
+            Err(ParseError::new(UnknownGlyph, Some('Q')).at(1, 13))
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(
+            Board::from_sfen("4k4/9/4P4/9/9/9/9/9/9 x G 1"),
+            Err(ParseError::new(InvalidField, Some('x')).at(1, 23))
+        );
+        assert_eq!(
+            Board::from_sfen("4k4/9/4Q4/9/9/9/9/9/9 b G 1"),
+            Err(ParseError::new(UnknownGlyph, Some('Q')).at(1, 8))
+        );
+        assert_eq!(
+            Board::from_sfen("4k4/9/4P4/9/9/9/9/9 b G 1"),
+            Err(ParseError::new(UnexpectedEnd, None).at(1, 20))
+        );
+        assert_eq!(
+            Board::from_sfen("4k4/9/4P4/4P4/9/9/9/9/9 b G 1")
+                .unwrap_err()
+                .reason,
+            DoublePawn
+        );
+        assert_eq!(
+            Board::from_sfen("4k4/9/4P4/9/9/9/9/9/9 b 999999999999999999999P 1"),
+            Err(ParseError::new(InvalidHandCount, Some('9')).at(1, 26))
+        );
+        assert_eq!(
+            Board::from_sfen("4k3L/9/4P4/9/9/9/9/9/9 b G 1")
+                .unwrap_err()
+                .reason,
+            DeadPiece
+        );
+        assert_eq!(
+            Board::from_sfen("4k4/9/4P4/9/9/9/9/7n1/9 b G 1")
+                .unwrap_err()
+                .reason,
+            DeadPiece
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use super::{Board, Coord, Piece, PieceKind, PieceStatus, BOARD_SIZE};
+use once_cell::sync::Lazy;
+use PieceStatus::*;
+
+/// Random numbers of Zobrist hashing, generated from a fixed seed so that keys are stable
+/// across builds and toolchains, which persisted databases rely on.
+struct Zobrist {
+    /// Indexed by square, kind, whether promoted and whether the piece is the enemy's.
+    squares: [[[[u64; 2]; 2]; 8]; BOARD_SIZE * BOARD_SIZE],
+    /// Indexed by kind, whether the hand is the enemy's and number of pieces.
+    /// Zero for an empty hand, so that kinds not in hand need not be visited.
+    hands: [[[u64; 19]; 2]; 8],
+}
+
+static ZOBRIST: Lazy<Zobrist> = Lazy::new(|| {
+    // SplitMix64
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    let mut zobrist = Zobrist {
+        squares: [[[[0; 2]; 2]; 8]; BOARD_SIZE * BOARD_SIZE],
+        hands: [[[0; 19]; 2]; 8],
+    };
+    for square in zobrist.squares.iter_mut() {
+        for kind in square.iter_mut() {
+            for is_changed in kind.iter_mut() {
+                for owner in is_changed.iter_mut() {
+                    *owner = next();
+                }
+            }
+        }
+    }
+    for kind in zobrist.hands.iter_mut() {
+        for owner in kind.iter_mut() {
+            for n in owner.iter_mut().skip(1) {
+                *n = next();
+            }
+        }
+    }
+    zobrist
+});
+
+fn square(kind: PieceKind, p: &Piece) -> u64 {
+    let index = p.coord.y as usize * BOARD_SIZE + p.coord.x as usize;
+    ZOBRIST.squares[index][kind as usize][p.is_changed as usize][(p.status == EnemyBoard) as usize]
+}
+
+fn hand(kind: PieceKind, is_mine: bool, n: usize) -> u64 {
+    ZOBRIST.hands[kind as usize][!is_mine as usize][n]
+}
+
+impl Board {
+    /// Computes the Zobrist hash of the pieces from scratch.
+    pub(crate) fn zobrist_hash(&self) -> u64 {
+        let mut hash = 0;
+        let mut hands = [[0; 2]; 8];
+        for (kind, _, p) in self.pieces.iter() {
+            match p.status {
+                MyBoard | EnemyBoard => hash ^= square(kind, p),
+                MyHand => hands[kind as usize][0] += 1,
+                EnemyHand => hands[kind as usize][1] += 1,
+            }
+        }
+        for (kind, counts) in hands.iter().enumerate() {
+            hash ^= hand(kind.into(), true, counts[0]) ^ hand(kind.into(), false, counts[1]);
+        }
+        hash
+    }
+
+    /// The Zobrist hash of the pieces on the board, leaving out the hands.
+    pub(crate) fn squares_hash(&self) -> u64 {
+        self.pieces
+            .iter()
+            .filter(|(_, _, p)| matches!(p.status, MyBoard | EnemyBoard))
+            .fold(0, |hash, (kind, _, p)| hash ^ square(kind, p))
+    }
+
+    fn my_hand_len(&self, kind: PieceKind) -> usize {
+        self.bitboards.hand(MyHand, kind)
+    }
+
+    /// Moves my piece `i` of `kind` to `to`, capturing the piece there if any.
+    /// The bitboards and the hash are updated incrementally, which is cheaper than `reload_bitboards`.
+    pub(crate) fn move_piece(&mut self, kind: PieceKind, i: usize, to: Coord, is_changed: bool) {
+        if let Some((&captured, captured_kind, captured_i)) = self.piece_at(&to) {
+            let n = self.my_hand_len(captured_kind);
+            self.hash ^= square(captured_kind, &captured)
+                ^ hand(captured_kind, true, n)
+                ^ hand(captured_kind, true, n + 1);
+            self.bitboards.remove(captured_kind, &captured);
+            self[captured_kind][captured_i] = Piece::catched(true);
+            self.bitboards
+                .insert(captured_kind, captured_i, &Piece::catched(true));
+        }
+        let from = self[kind][i];
+        let to = Piece::moved(to, is_changed);
+        self.hash ^= square(kind, &from) ^ square(kind, &to);
+        self[kind][i] = to;
+        self.bitboards.remove(kind, &from);
+        self.bitboards.insert(kind, i, &to);
+    }
+
+    /// Drops my piece `i` of `kind` from my hand to the empty square `to`.
+    pub(crate) fn put_piece(&mut self, kind: PieceKind, i: usize, to: Coord) {
+        let n = self.my_hand_len(kind);
+        let to = Piece::moved(to, false);
+        self.hash ^= hand(kind, true, n) ^ hand(kind, true, n - 1) ^ square(kind, &to);
+        let from = self[kind][i];
+        self.bitboards.remove(kind, &from);
+        self[kind][i] = to;
+        self.bitboards.insert(kind, i, &to);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental() {
+        let board = Board::parsed(include_str!("../../examples/five.txt")).unwrap();
+        let mut n = 0;
+        for (child, _) in board.create_all_next_boards().unwrap() {
+            assert_eq!(child.hash, child.zobrist_hash());
+            let mut reloaded = child.clone();
+            reloaded.reload_bitboards();
+            assert_eq!(child.bitboards, reloaded.bitboards);
+            for (kind, i, p) in child.pieces.iter().filter(|(_, _, p)| p.status.on_board()) {
+                assert_eq!(child.piece_at(&p.coord), Some((p, kind, i)));
+            }
+            n += 1;
+        }
+        assert!(n > 0);
+        assert_ne!(board.hash, board.reversed().hash);
+        // Fixed by the seed, whatever the toolchain.
+        assert_eq!(Board::first().hash, 2031505572098234444);
+    }
+}
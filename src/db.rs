@@ -1,26 +1,167 @@
-use std::path::Path;
+use std::{
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::{Mutex, MutexGuard},
+};
 
+use crate::StorageError;
 use bincode::{self, Decode, Encode};
-pub(crate) use rocksdb::DB;
-use rocksdb::{self, BlockBasedOptions, Cache, Options};
+use rocksdb::{self, BlockBasedOptions, Cache, DBCompressionType, Options, WriteOptions};
 pub(crate) type Key = [u8; 8];
 
-pub(crate) fn open_with_cache<P: AsRef<Path>>(
+/// A file that marks a directory as a database of this solver, so that only such directories are reset.
+const MARKER: &str = "DF_PN";
+/// A file of every RocksDB directory, by which the databases written before `MARKER` are told
+/// when their reset is asked for explicitly.
+const ROCKSDB_CURRENT: &str = "CURRENT";
+
+/// Compression of the blocks of the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "snappy" => Ok(Compression::Snappy),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression: {s}")),
+        }
+    }
+}
+
+/// Tuning of the database. The default is that of RocksDB with a 12GiB block cache.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageConfig {
+    /// Size in bytes of the block cache.
+    pub cache_size: usize,
+    /// Size in bytes of a memtable, the RocksDB default if `None`.
+    pub write_buffer_size: Option<usize>,
+    /// The RocksDB default if `None`.
+    pub compression: Option<Compression>,
+    /// Skip the write-ahead log. Writes are faster, but those not flushed yet are lost if the process dies.
+    pub disable_wal: bool,
+    /// Bits per key of a bloom filter, which saves disk reads for positions not in the database.
+    pub bloom_filter_bits: Option<f64>,
+    /// Defer compactions as for a bulk load, which speeds up writing many new positions.
+    pub bulk_load: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            cache_size: 1024 * 1024 * 1024 * 12, // 12GiB
+            write_buffer_size: None,
+            compression: None,
+            disable_wal: false,
+            bloom_filter_bits: None,
+            bulk_load: false,
+        }
+    }
+}
+
+impl StorageConfig {
+    fn options(&self) -> Options {
+        let cache = Cache::new_lru_cache(self.cache_size);
+        let mut table_options = BlockBasedOptions::default();
+        table_options.set_block_cache(&cache);
+        if let Some(bits) = self.bloom_filter_bits {
+            table_options.set_bloom_filter(bits, false);
+        }
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.set_block_based_table_factory(&table_options);
+        if let Some(size) = self.write_buffer_size {
+            opts.set_write_buffer_size(size);
+        }
+        if let Some(compression) = self.compression {
+            opts.set_compression_type(match compression {
+                Compression::None => DBCompressionType::None,
+                Compression::Snappy => DBCompressionType::Snappy,
+                Compression::Lz4 => DBCompressionType::Lz4,
+                Compression::Zstd => DBCompressionType::Zstd,
+            });
+        }
+        if self.bulk_load {
+            opts.prepare_for_bulk_load();
+        }
+        opts
+    }
+}
+
+/// Number of locks that the keys are spread over, so that threads reading and then writing a key take turns.
+const KEY_LOCKS: usize = 64;
+
+pub(crate) struct DB {
+    inner: rocksdb::DB,
+    write_options: WriteOptions,
+    key_locks: Vec<Mutex<()>>,
+}
+
+pub(crate) fn is_solver_db(path: &Path) -> bool {
+    path.join(MARKER).exists()
+}
+
+fn is_empty_dir(path: &Path) -> Result<bool, StorageError> {
+    Ok(path.read_dir()?.next().is_none())
+}
+
+/// Removes the database at `path`. A directory that is neither empty nor a database of this solver
+/// is left as it is, as it may be anything given by mistake. A database written before the solver
+/// marked its databases has no mark, and is taken for one by any RocksDB directory only if `unmarked` is set.
+pub fn reset_db<P: AsRef<Path>>(path: P, unmarked: bool) -> Result<(), StorageError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(());
+    }
+    let is_old_db = unmarked && path.join(ROCKSDB_CURRENT).exists();
+    if !is_solver_db(path) && !is_old_db && !is_empty_dir(path)? {
+        return Err(StorageError::NotSolverDatabase(path.to_path_buf()));
+    }
+    fs::remove_dir_all(path)?;
+    Ok(())
+}
+
+pub(crate) fn open<P: AsRef<Path>>(
     path: P,
     reset_db: bool,
-) -> Result<DB, rocksdb::Error> {
-    if reset_db && path.as_ref().exists() {
-        std::fs::remove_dir_all(&path).unwrap();
+    config: &StorageConfig,
+) -> Result<DB, StorageError> {
+    let path = path.as_ref();
+    if reset_db {
+        self::reset_db(path, false)?;
     }
 
-    let cache = Cache::new_lru_cache(1024 * 1024 * 1024 * 12); // 12GiB
-    let mut table_options = BlockBasedOptions::default();
-    table_options.set_block_cache(&cache);
-    let mut opts = Options::default();
-    opts.create_if_missing(true);
-    opts.set_block_based_table_factory(&table_options);
+    // Only a database created here is marked, not a directory that was there before.
+    let is_new = !path.exists() || is_empty_dir(path)?;
+    let inner = rocksdb::DB::open(&config.options(), path)?;
+    if is_new {
+        fs::write(path.join(MARKER), "")?;
+    }
+    let mut write_options = WriteOptions::default();
+    write_options.disable_wal(config.disable_wal);
+    Ok(DB {
+        inner,
+        write_options,
+        key_locks: (0..KEY_LOCKS).map(|_| Mutex::new(())).collect(),
+    })
+}
 
-    DB::open(&opts, path)
+/// Estimated number of entities, which is cheap to get and accurate enough for progress reports.
+pub(crate) fn estimate_len(db: &DB) -> u64 {
+    db.inner
+        .property_int_value("rocksdb.estimate-num-keys")
+        .unwrap()
+        .unwrap_or(0)
 }
 
 pub(crate) trait Entity: Encode + Decode {
@@ -31,19 +172,77 @@ pub(crate) fn get_entity<T>(db: &DB, key: &Key) -> Option<T>
 where
     T: Entity,
 {
-    let Some(bytes) = db.get(key).unwrap() else {
-        return None;
-    };
-    let (entity, _): (T, _) =
-        bincode::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
-    Some(entity)
+    get_value(db, key)
 }
 
 pub(crate) fn put_entity<T>(db: &DB, entity: &T)
 where
     T: Entity,
 {
-    let key = entity.get_key();
-    let bytes = bincode::encode_to_vec(entity, bincode::config::standard()).unwrap();
-    db.put(&key, &bytes).unwrap();
+    put_value(db, &entity.get_key(), entity);
+}
+
+/// Locks `key` against the other threads that lock it, to read and write it in one step.
+pub(crate) fn lock_key<'a>(db: &'a DB, key: &Key) -> MutexGuard<'a, ()> {
+    let i = u64::from_le_bytes(*key) as usize % KEY_LOCKS;
+    db.key_locks[i].lock().unwrap()
+}
+
+/// Gets a value stored under any key. Keys of another length than `Key` never clash with entities.
+pub(crate) fn get_value<T>(db: &DB, key: &[u8]) -> Option<T>
+where
+    T: Decode,
+{
+    let bytes = db.inner.get(key).unwrap()?;
+    let (value, _): (T, _) =
+        bincode::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+    Some(value)
+}
+
+pub(crate) fn put_value<T>(db: &DB, key: &[u8], value: &T)
+where
+    T: Encode,
+{
+    let bytes = bincode::encode_to_vec(value, bincode::config::standard()).unwrap();
+    db.inner.put_opt(key, &bytes, &db.write_options).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_db() {
+        let path = Path::new("/tmp/df_pn.test.reset.rocksdb");
+        let _ = fs::remove_dir_all(path);
+        fs::create_dir_all(path).unwrap();
+        fs::write(path.join("notes.txt"), "not a database").unwrap();
+        assert!(matches!(
+            reset_db(path, true),
+            Err(StorageError::NotSolverDatabase(_))
+        ));
+        assert!(path.join("notes.txt").exists());
+
+        fs::remove_file(path.join("notes.txt")).unwrap();
+        let db = open(path, true, &StorageConfig::default()).unwrap();
+        put_value(&db, b"key", &1u32);
+        drop(db);
+        let db = open(path, false, &StorageConfig::default()).unwrap();
+        assert_eq!(get_value::<u32>(&db, b"key"), Some(1));
+        drop(db);
+
+        reset_db(path, false).unwrap();
+        assert!(!path.exists());
+
+        // A database written before the marker is reset only when asked for.
+        fs::create_dir_all(path).unwrap();
+        fs::write(path.join(ROCKSDB_CURRENT), "MANIFEST-000001").unwrap();
+        assert!(matches!(
+            reset_db(path, false),
+            Err(StorageError::NotSolverDatabase(_))
+        ));
+        assert!(!is_solver_db(path));
+        reset_db(path, true).unwrap();
+        assert!(!path.exists());
+    }
 }
@@ -1,6 +1,135 @@
 use crate::Board;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub(crate) enum Error {
-    CatchKing(Board),
+    CatchKing(Box<Board>),
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorReason {
+    /// A character that is not a piece, side marker or separator where one is expected.
+    UnknownGlyph,
+    /// A hand count that is not a number, or a promoted piece in a hand.
+    InvalidHandCount,
+    /// The number of pieces of a kind is not that of a complete set.
+    WrongPieceTotal { len: usize, expected: usize },
+    /// There must be one or two kings.
+    WrongKingCount { len: usize },
+    /// A pawn on the last rank.
+    PawnOnLastRank,
+    /// A lance on the last rank, or a knight on the last two ranks.
+    DeadPiece,
+    /// Two unpromoted pawns of the same side in a file.
+    DoublePawn,
+    /// The input ends or a line ends before the board is complete.
+    UnexpectedEnd,
+    /// A malformed field, such as the side to move of a SFEN string.
+    InvalidField,
+    /// A move that cannot be played in the position.
+    IllegalMove,
+}
+
+/// An error while parsing a board. `line` and `column` are 1-based and are `None` for errors
+/// concerning the whole board, such as a wrong number of pieces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub char: Option<char>,
+    pub reason: ParseErrorReason,
+}
+
+impl ParseError {
+    pub(crate) fn new(reason: ParseErrorReason, char: Option<char>) -> ParseError {
+        ParseError {
+            line: None,
+            column: None,
+            char,
+            reason,
+        }
+    }
+
+    pub(crate) fn at(self, line: usize, column: usize) -> ParseError {
+        ParseError {
+            line: Some(line),
+            column: Some(column),
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for ParseErrorReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorReason::UnknownGlyph => write!(f, "unknown glyph"),
+            ParseErrorReason::InvalidHandCount => write!(f, "invalid hand count"),
+            ParseErrorReason::WrongPieceTotal { len, expected } => {
+                write!(f, "{len} pieces, expected {expected}")
+            }
+            ParseErrorReason::WrongKingCount { len } => write!(f, "{len} kings, expected 1 or 2"),
+            ParseErrorReason::PawnOnLastRank => write!(f, "pawn on the last rank"),
+            ParseErrorReason::DeadPiece => write!(f, "piece without legal moves"),
+            ParseErrorReason::DoublePawn => write!(f, "two unpromoted pawns in a file"),
+            ParseErrorReason::UnexpectedEnd => write!(f, "unexpected end"),
+            ParseErrorReason::InvalidField => write!(f, "invalid field"),
+            ParseErrorReason::IllegalMove => write!(f, "illegal move"),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {line}")?;
+            if let Some(column) = self.column {
+                write!(f, ", column {column}")?;
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.reason)?;
+        if let Some(c) = self.char {
+            write!(f, " `{c}`")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An error of the database.
+#[derive(Debug)]
+pub enum StorageError {
+    /// A directory to reset that is not a database of this solver.
+    NotSolverDatabase(PathBuf),
+    Io(std::io::Error),
+    RocksDb(String),
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<rocksdb::Error> for StorageError {
+    fn from(e: rocksdb::Error) -> Self {
+        StorageError::RocksDb(e.into_string())
+    }
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::NotSolverDatabase(path) => write!(
+                f,
+                "{} is not a database of df-pn, remove it yourself to reset it",
+                path.display()
+            ),
+            StorageError::Io(e) => write!(f, "{e}"),
+            StorageError::RocksDb(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
@@ -4,11 +4,16 @@ mod error;
 mod result;
 mod shared;
 
-pub(crate) use board::{Coord, NextBoardKind, Piece, PieceKind, BOARD_SIZE};
+pub(crate) use board::{Coord, Piece, PieceKind, BOARD_SIZE};
 pub(crate) use error::Error;
 pub(crate) use result::Result;
 
 #[cfg(test)]
 pub(crate) use board::PieceStatus;
 
-pub use board::{Board, CheckmateResult};
+pub use board::{
+    AlternativeMate, Board, CheckmateResult, Checkpoint, MismatchReason, Move, MoveTree, Progress,
+    ProgressObserver, SearchOptions, Verification,
+};
+pub use db::{reset_db, Compression, StorageConfig};
+pub use error::{ParseError, ParseErrorReason, StorageError};
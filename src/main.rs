@@ -1,10 +1,17 @@
 use clap::Parser;
-use df_pn::{Board, CheckmateResult};
-use std::fs::read_to_string;
+use df_pn::{
+    reset_db, AlternativeMate, Board, CheckmateResult, Checkpoint, Compression, MismatchReason,
+    Move, ProgressObserver, SearchOptions, StorageConfig, Verification,
+};
+use std::{
+    fs::{read_to_string, write},
+    process::exit,
+    time::{Duration, Instant},
+};
 
 #[derive(Parser)]
 struct Args {
-    /// The board to check.
+    /// The board to check, or a KIF/BOD diagram.
     /// Examples: https://github.com/malt03/df-pn-rust/blob/main/examples
     board_file: String,
 
@@ -18,49 +25,264 @@ struct Args {
     #[arg(short = 'd', long)]
     max_depth: Option<usize>,
 
+    /// Give up the search after this many seconds.
+    #[arg(short, long)]
+    time_limit: Option<u64>,
+
+    /// Dump the most promising line to stderr every this many searches.
+    #[arg(long, num_args = 0..=1, default_missing_value = "50000")]
+    progress: Option<usize>,
+
     /// The path to the database.
     #[arg(long, default_value_t = String::from("/tmp/df_pn.rocksdb"))]
     db_path: String,
 
+    /// Size of the block cache of the database in MiB.
+    #[arg(long, default_value_t = 12 * 1024)]
+    cache_size: usize,
+
+    /// Size of a write buffer of the database in MiB.
+    #[arg(long)]
+    write_buffer_size: Option<usize>,
+
+    /// Compression of the database: none, snappy, lz4 or zstd.
+    #[arg(long)]
+    compression: Option<Compression>,
+
+    /// Do not write the write-ahead log of the database. Faster, but a crash may lose recent positions.
+    #[arg(long, default_value_t = false)]
+    disable_wal: bool,
+
+    /// Bits per key of a bloom filter of the database.
+    #[arg(long)]
+    bloom_filter: Option<f64>,
+
+    /// Defer the compactions of the database as for a bulk load.
+    #[arg(long, default_value_t = false)]
+    bulk_load: bool,
+
+    /// Also leave out of the answer the defender's undefended pieces dropped between the king
+    /// and a distant checker, once taking them is found to mate.
+    #[arg(long, default_value_t = false)]
+    exclude_useless_interpositions: bool,
+
+    /// Number of threads to search with.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+
+    /// Keep the positions in a table of this many MiB in memory instead of the database.
+    #[arg(long)]
+    memory: Option<usize>,
+
     /// Do not reset the database.
     #[arg(long, default_value_t = false)]
     no_reset_db: bool,
+
+    /// Also reset a database written by an older df-pn, which did not mark its databases.
+    /// Any RocksDB directory is taken for one.
+    #[arg(long, default_value_t = false, conflicts_with = "no_reset_db")]
+    reset_unmarked_db: bool,
+
+    /// Continue the search of the same board saved in the database by an earlier run.
+    #[arg(long, default_value_t = false, conflicts_with = "memory")]
+    resume: bool,
+
+    /// Save the search to the database every this many searches, so that it can be resumed.
+    #[arg(long, default_value_t = 1_000_000)]
+    checkpoint: usize,
+
+    /// Put the pieces missing from the board file into the enemy's hand.
+    #[arg(long, default_value_t = false)]
+    partial: bool,
+
+    /// Write the mate to this path as a KIF file.
+    #[arg(long)]
+    kif: Option<String>,
+
+    /// Write the mate to this path as a KI2 file.
+    #[arg(long)]
+    ki2: Option<String>,
+
+    /// Include the defender's alternative moves in the KIF/KI2 files.
+    #[arg(long, default_value_t = false)]
+    variations: bool,
+
+    /// Also try the other moves of the attacker on the main line and report those that mate (余詰).
+    #[arg(long, default_value_t = false)]
+    verify: bool,
 }
 
 fn main() {
     let args = Args::parse();
     let body = read_to_string(&args.board_file)
-        .expect(format!("failed to read file: {}", args.board_file).as_str());
-    let board = Board::parsed(body);
+        .unwrap_or_else(|_| panic!("failed to read file: {}", args.board_file));
+    let board = if body.contains("の持駒：") {
+        Board::from_bod(body)
+    } else if args.partial {
+        Board::parsed_partial(body)
+    } else {
+        Board::parsed(body)
+    };
+    let board = board.unwrap_or_else(|e| {
+        eprintln!("failed to parse {}: {e}", args.board_file);
+        exit(1);
+    });
+
+    let options = SearchOptions {
+        num_searches: args.num_searches,
+        max_depth: args.max_depth.map(|d| d.saturating_add(2)),
+        deadline: args
+            .time_limit
+            .map(|t| Instant::now() + Duration::from_secs(t)),
+        progress: args.progress.map(console_progress),
+        table_size: args.memory.map(|mb| mb * 1024 * 1024),
+        resume: args.resume,
+        threads: args.threads,
+        exclude_useless_interpositions: args.exclude_useless_interpositions,
+        checkpoint_interval: Some(args.checkpoint),
+        storage: StorageConfig {
+            cache_size: args.cache_size * 1024 * 1024,
+            write_buffer_size: args.write_buffer_size.map(|mb| mb * 1024 * 1024),
+            compression: args.compression,
+            disable_wal: args.disable_wal,
+            bloom_filter_bits: args.bloom_filter,
+            bulk_load: args.bulk_load,
+        },
+        ..Default::default()
+    };
+    let reset = !args.no_reset_db && !args.resume && args.memory.is_none();
+    if reset {
+        if let Err(e) = reset_db(&args.db_path, args.reset_unmarked_db) {
+            eprintln!("{e}");
+            exit(1);
+        }
+    }
+    if args.resume {
+        let Some(checkpoint) = Checkpoint::load(&args.db_path) else {
+            eprintln!("{} holds no search to resume", args.db_path);
+            exit(1);
+        };
+        match checkpoint.matches(&board, &options) {
+            Ok(()) => println!("resuming after {} searches", checkpoint.searches),
+            Err(MismatchReason::Board) => {
+                eprintln!("{} holds the search of another board", args.db_path);
+                exit(1);
+            }
+            Err(MismatchReason::MaxDepth(depth)) => {
+                let depth = depth.map_or(String::from("no limit"), |d| {
+                    d.saturating_sub(2).to_string()
+                });
+                eprintln!("{} was searched with max depth {depth}", args.db_path);
+                exit(1);
+            }
+        }
+    }
 
     println!("{board}\n\n=================================\n");
-    let result = board.get_checkmate_boards(
-        args.db_path,
-        !args.no_reset_db,
-        args.num_searches,
-        args.max_depth.map(|d| d + 2),
-    );
+    let result = if args.verify {
+        board.verify_checkmate(args.db_path, false, &options, args.variations)
+    } else {
+        match board.get_checkmate_tree(args.db_path, false, &options, args.variations) {
+            CheckmateResult::Checkmate(tree, count) => CheckmateResult::Checkmate(
+                Verification {
+                    tree,
+                    alternatives: Vec::new(),
+                },
+                count,
+            ),
+            CheckmateResult::NotCheckmate(tree, count) => CheckmateResult::NotCheckmate(
+                Verification {
+                    tree,
+                    alternatives: Vec::new(),
+                },
+                count,
+            ),
+            CheckmateResult::Unproven(count) => CheckmateResult::Unproven(count),
+        }
+    };
     let is_checkmate = result.is_checkmate();
     match result {
-        CheckmateResult::Checkmate(boards, count)
-        | CheckmateResult::NotCheckmate(boards, count) => {
+        CheckmateResult::Checkmate(Verification { tree, alternatives }, count)
+        | CheckmateResult::NotCheckmate(Verification { tree, alternatives }, count) => {
             println!("\n\n");
             println!("===========================================");
-            println!("               best boards");
+            println!("               best moves");
             println!("===========================================");
             println!("\n\n");
-            for (i, board) in boards.into_iter().rev().enumerate() {
-                println!(
-                    "{}\n\n=================================\n",
-                    if i % 2 == 0 { board } else { board.reversed() }
-                );
+            let moves = tree.main_line();
+            println!("{}", format_moves(&moves));
+            if args.verify && is_checkmate {
+                println!("{}", format_alternatives(&moves, &alternatives));
             }
             if is_checkmate {
+                if let Some(path) = &args.kif {
+                    write(path, board.to_kif(&tree))
+                        .unwrap_or_else(|_| panic!("failed to write file: {path}"));
+                }
+                if let Some(path) = &args.ki2 {
+                    write(path, board.to_ki2(&tree))
+                        .unwrap_or_else(|_| panic!("failed to write file: {path}"));
+                }
                 println!("checkmate found in {} searches", count);
             } else {
                 println!("not checkmate found in {} searches", count);
             }
         }
-        CheckmateResult::Unproven => println!("could not prove checkmate nor not checkmate"),
+        CheckmateResult::Unproven(count) => println!(
+            "could not prove checkmate nor not checkmate in {} searches",
+            count
+        ),
+    }
+}
+
+/// Dumps the best line and the statistics to stderr.
+fn console_progress(interval: usize) -> ProgressObserver {
+    ProgressObserver::new(interval, |progress| {
+        for board in progress.best_line() {
+            eprintln!("{board}\n=================================");
+        }
+        eprintln!(
+            "{} searches, pn: {}, dn: {}, {} nodes, {:.1}s",
+            progress.searches,
+            progress.pn,
+            progress.dn,
+            progress.nodes(),
+            progress.elapsed.as_secs_f64()
+        );
+    })
+}
+
+fn format_alternatives(moves: &[Move], alternatives: &[AlternativeMate]) -> String {
+    if alternatives.is_empty() {
+        return String::from("no alternative mates");
+    }
+    let mut lines = Vec::new();
+    for alternative in alternatives {
+        let prev = alternative.ply.checked_sub(1).map(|i| &moves[i]);
+        let len = alternative
+            .len
+            .map_or(String::from("mates"), |len| format!("mates in {len}"));
+        let kind = if alternative.is_finishing {
+            "finishing dual"
+        } else {
+            "alternative mate"
+        };
+        lines.push(format!(
+            "{kind}: move {} ▲{} {len}",
+            alternative.ply + 1,
+            alternative.mv.to_japanese(prev)
+        ));
+    }
+    lines.join("\n")
+}
+
+fn format_moves(moves: &[Move]) -> String {
+    let mut prev = None;
+    let mut strs = Vec::new();
+    for (i, mv) in moves.iter().enumerate() {
+        let mark = if i % 2 == 0 { '▲' } else { '△' };
+        strs.push(format!("{mark}{}", mv.to_japanese(prev)));
+        prev = Some(mv);
     }
+    strs.join(" ")
 }
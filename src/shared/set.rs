@@ -6,7 +6,7 @@ use std::{
 
 use bincode::{BorrowDecode, Decode, Encode};
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone)]
 pub(crate) struct Set<T> {
     elements: Vec<T>,
 }